@@ -0,0 +1,57 @@
+//! Measures the overhead of dispatching a call through `axum::handle_server_fn`, for a server
+//! function without any middleware and for one wrapped in several layers of middleware,
+//! against a baseline of running the server function directly.
+//!
+//! Run with `cargo bench --features axum,url,json,browser`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use futures::executor::block_on;
+use server_fn_macro_default::server;
+use server_fns::{
+    axum::{handle_server_fn, register_middleware_for_prefix},
+    axum_export::{body::Body, http::Request},
+    middleware::from_fn,
+    ServerFn, ServerFnError,
+};
+
+#[server(endpoint = "/bare", input = GetUrl)]
+pub async fn bare() -> Result<(), ServerFnError> {
+    Ok(())
+}
+
+#[server(endpoint = "/layered/fn", input = GetUrl)]
+#[middleware(from_fn(|req, next| next.run(req)))]
+#[middleware(from_fn(|req, next| next.run(req)))]
+#[middleware(from_fn(|req, next| next.run(req)))]
+#[middleware(from_fn(|req, next| next.run(req)))]
+pub async fn layered() -> Result<(), ServerFnError> {
+    Ok(())
+}
+
+fn dispatch(c: &mut Criterion) {
+    for _ in 0..4 {
+        register_middleware_for_prefix("/api/layered", from_fn(|req, next| next.run(req)));
+    }
+
+    c.bench_function("run directly", |b| {
+        b.iter(|| {
+            let req = Request::get("/api/bare").body(Body::empty()).unwrap();
+            block_on(Bare::run_on_server(req))
+        })
+    });
+    c.bench_function("dispatch without middleware", |b| {
+        b.iter(|| {
+            let req = Request::get("/api/bare").body(Body::empty()).unwrap();
+            block_on(handle_server_fn(req))
+        })
+    });
+    c.bench_function("dispatch with 8 middleware layers", |b| {
+        b.iter(|| {
+            let req = Request::get("/api/layered/fn").body(Body::empty()).unwrap();
+            block_on(handle_server_fn(req))
+        })
+    });
+}
+
+criterion_group!(benches, dispatch);
+criterion_main!(benches);
@@ -0,0 +1,523 @@
+//! Sending several server function calls in one HTTP request.
+//!
+//! A page that calls ten small server functions at once pays for ten round trips. With
+//! [`BatchClient`], calls that are made in the same tick are sent together in a single
+//! request to the batch endpoint, which runs each of them through the usual middleware and
+//! sends back all of their results. Nothing changes for the code that calls them.
+//!
+//! To batch calls to a server function, wrap its client in [`BatchClient`]:
+//!
+//! ```ignore
+//! use server_fns::{batch::BatchClient, client::browser::BrowserClient};
+//!
+//! #[server(client = BatchClient<BrowserClient>)]
+//! pub async fn get_user(id: u32) -> Result<User, ServerFnError> {
+//!     todo!()
+//! }
+//!
+//! // sent as one request
+//! let (a, b) = futures::join!(get_user(1), get_user(2));
+//! ```
+//!
+//! Then mount the batch handler on the server, at [`DEFAULT_BATCH_PATH`] or the path set
+//! with [`set_batch_path`]. This is currently only available with the `axum` integration,
+//! so this module doesn't exist in builds that enable `actix` without `axum`:
+//!
+//! ```ignore
+//! let app = Router::new()
+//!     .route("/api/_batch", post(server_fns::axum::handle_batch))
+//!     .route("/api/*fn_name", post(server_fns::axum::handle_server_fn));
+//! ```
+//!
+//! A call that is the only one in its tick is sent on its own, as usual, and more than
+//! [`MAX_BATCH_SIZE`] calls are split into several batches. Multipart forms can't be
+//! batched, and streaming responses are buffered before they are returned. Each call in a
+//! batch can still fail on its own, with its own status code.
+
+use crate::{
+    client::Client,
+    codec::MessageStream,
+    error::{NoCustomError, ServerFnError},
+    redirect::REDIRECT_HEADER,
+    request::ClientReq,
+    response::ClientRes,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::Bytes;
+use dashmap::DashMap;
+use futures::{
+    channel::oneshot,
+    future::{BoxFuture, Shared},
+    FutureExt, Stream,
+};
+use once_cell::sync::Lazy;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    any::TypeId,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    sync::{Arc, Mutex, OnceLock},
+    task::{Context, Poll},
+};
+
+/// The path of the batch endpoint, if it has not been set with [`set_batch_path`].
+pub const DEFAULT_BATCH_PATH: &str = "/api/_batch";
+
+/// The most calls that the batch endpoint accepts in one batch. [`BatchClient`] splits
+/// larger batches into several requests.
+pub const MAX_BATCH_SIZE: usize = 64;
+
+/// The headers that each call in a batch can set for itself. All of the other headers of a
+/// call are those of the batch request.
+#[cfg(feature = "axum")]
+pub(crate) const ENTRY_HEADERS: [&str; 4] = [
+    "content-type",
+    "accept",
+    "traceparent",
+    crate::idempotency::IDEMPOTENCY_KEY_HEADER,
+];
+
+static BATCH_PATH: OnceLock<&'static str> = OnceLock::new();
+
+/// Sets the path of the batch endpoint that [`BatchClient`] sends batches to.
+///
+/// This can only be set once. If it has already been set, `path` is returned as the error.
+pub fn set_batch_path(path: &'static str) -> Result<(), &'static str> {
+    BATCH_PATH.set(path)
+}
+
+fn batch_path() -> &'static str {
+    BATCH_PATH.get().copied().unwrap_or(DEFAULT_BATCH_PATH)
+}
+
+/// One call in a batch, as it is sent to the server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct BatchEntry {
+    pub method: String,
+    pub path: String,
+    pub query: Option<String>,
+    pub headers: Vec<(String, String)>,
+    /// Base64-encoded, as the arguments may be binary.
+    pub body: String,
+}
+
+/// The result of one call in a batch, as it is sent back to the client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct BatchEntryResult {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    /// Base64-encoded, as the result may be binary.
+    pub body: String,
+}
+
+#[cfg(feature = "axum")]
+impl BatchEntry {
+    /// The decoded body of the call.
+    pub fn body(&self) -> Result<Bytes, base64::DecodeError> {
+        STANDARD.decode(&self.body).map(Bytes::from)
+    }
+}
+
+#[cfg(feature = "axum")]
+impl BatchEntryResult {
+    pub fn new(status: u16, headers: Vec<(String, String)>, body: &[u8]) -> Self {
+        Self {
+            status,
+            headers,
+            body: STANDARD.encode(body),
+        }
+    }
+}
+
+/// A request made with [`BatchClient`], which may be sent on its own or in a batch.
+#[derive(Debug, Clone)]
+pub struct BatchRequest {
+    method: &'static str,
+    path: String,
+    query: Option<String>,
+    headers: Vec<(String, String)>,
+    body: Bytes,
+}
+
+impl BatchRequest {
+    fn new(
+        method: &'static str,
+        path: &str,
+        accepts: &str,
+        content_type: &str,
+        query: Option<&str>,
+        body: Bytes,
+    ) -> Self {
+        Self {
+            method,
+            path: path.to_string(),
+            query: query.map(String::from),
+            headers: vec![
+                ("content-type".to_string(), content_type.to_string()),
+                ("accept".to_string(), accepts.to_string()),
+            ],
+            body,
+        }
+    }
+
+    fn header(&self, name: &str) -> &str {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+            .unwrap_or_default()
+    }
+
+    fn into_entry(self) -> BatchEntry {
+        BatchEntry {
+            method: self.method.to_string(),
+            path: self.path,
+            query: self.query,
+            headers: self.headers,
+            body: STANDARD.encode(&self.body),
+        }
+    }
+
+    /// Converts this into a request for the underlying client, to be sent on its own.
+    fn into_client_req<R: ClientReq<CustErr>, CustErr>(self) -> Result<R, ServerFnError<CustErr>> {
+        let accepts = self.header("accept").to_string();
+        let content_type = self.header("content-type").to_string();
+        let mut req = match self.method {
+            "GET" => R::try_new_get(
+                &self.path,
+                &accepts,
+                &content_type,
+                self.query.as_deref().unwrap_or_default(),
+            )?,
+            _ => R::try_new_post_bytes(&self.path, &accepts, &content_type, self.body)?,
+        };
+        for (name, value) in &self.headers {
+            if !name.eq_ignore_ascii_case("accept") && !name.eq_ignore_ascii_case("content-type") {
+                req = req.try_add_header(name, value)?;
+            }
+        }
+        Ok(req)
+    }
+}
+
+impl<CustErr> ClientReq<CustErr> for BatchRequest {
+    type FormData = ();
+
+    fn try_new_get(
+        path: &str,
+        accepts: &str,
+        content_type: &str,
+        query: &str,
+    ) -> Result<Self, ServerFnError<CustErr>> {
+        Ok(Self::new(
+            "GET",
+            path,
+            accepts,
+            content_type,
+            Some(query),
+            Bytes::new(),
+        ))
+    }
+
+    fn try_new_post(
+        path: &str,
+        accepts: &str,
+        content_type: &str,
+        body: String,
+    ) -> Result<Self, ServerFnError<CustErr>> {
+        Ok(Self::new(
+            "POST",
+            path,
+            accepts,
+            content_type,
+            None,
+            Bytes::from(body),
+        ))
+    }
+
+    fn try_new_post_bytes(
+        path: &str,
+        accepts: &str,
+        content_type: &str,
+        body: Bytes,
+    ) -> Result<Self, ServerFnError<CustErr>> {
+        Ok(Self::new("POST", path, accepts, content_type, None, body))
+    }
+
+    fn try_new_multipart(
+        _path: &str,
+        _accepts: &str,
+        _body: Self::FormData,
+    ) -> Result<Self, ServerFnError<CustErr>> {
+        Err(ServerFnError::Request(
+            "multipart forms can't be sent in a batch".into(),
+        ))
+    }
+
+    fn try_add_header(mut self, name: &str, value: &str) -> Result<Self, ServerFnError<CustErr>> {
+        self.headers
+            .retain(|(header, _)| !header.eq_ignore_ascii_case(name));
+        self.headers.push((name.to_string(), value.to_string()));
+        Ok(self)
+    }
+}
+
+/// The response to a call made with [`BatchClient`].
+#[derive(Debug, Clone)]
+pub struct BatchResponse {
+    status: u16,
+    status_text: String,
+    location: String,
+    has_redirect: bool,
+    body: Bytes,
+}
+
+impl BatchResponse {
+    async fn from_client_res<R: ClientRes<CustErr>, CustErr>(
+        res: R,
+    ) -> Result<Self, ServerFnError<CustErr>> {
+        Ok(Self {
+            status: res.status(),
+            status_text: res.status_text(),
+            location: res.location(),
+            has_redirect: res.has_redirect(),
+            body: res.try_into_bytes().await?,
+        })
+    }
+
+    fn from_result<CustErr>(result: BatchEntryResult) -> Result<Self, ServerFnError<CustErr>> {
+        let header = |name: &str| {
+            result
+                .headers
+                .iter()
+                .find(|(header, _)| header.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.clone())
+        };
+        Ok(Self {
+            status: result.status,
+            status_text: result.status.to_string(),
+            location: header("location").unwrap_or_default(),
+            has_redirect: header(REDIRECT_HEADER).is_some(),
+            body: STANDARD
+                .decode(&result.body)
+                .map(Bytes::from)
+                .map_err(|e| ServerFnError::Deserialization(e.to_string()))?,
+        })
+    }
+}
+
+impl<CustErr> ClientRes<CustErr> for BatchResponse {
+    async fn try_into_string(self) -> Result<String, ServerFnError<CustErr>> {
+        String::from_utf8(self.body.to_vec())
+            .map_err(|e| ServerFnError::Deserialization(e.to_string()))
+    }
+
+    async fn try_into_bytes(self) -> Result<Bytes, ServerFnError<CustErr>> {
+        Ok(self.body)
+    }
+
+    fn try_into_stream(
+        self,
+    ) -> Result<
+        impl Stream<Item = Result<Bytes, ServerFnError>> + Send + 'static,
+        ServerFnError<CustErr>,
+    > {
+        Ok(futures::stream::once(async move { Ok(self.body) }))
+    }
+
+    fn status(&self) -> u16 {
+        self.status
+    }
+
+    fn status_text(&self) -> String {
+        self.status_text.clone()
+    }
+
+    fn location(&self) -> String {
+        self.location.clone()
+    }
+
+    fn has_redirect(&self) -> bool {
+        self.has_redirect
+    }
+}
+
+/// A call waiting to be sent, and where to send its response or (JSON-encoded) error.
+type Waiting = (BatchRequest, oneshot::Sender<Result<BatchResponse, String>>);
+
+/// The calls waiting to be sent by one underlying client, and the batch that will send them.
+#[derive(Default)]
+struct Pending {
+    waiting: Vec<Waiting>,
+    batch: Option<Shared<BoxFuture<'static, ()>>>,
+}
+
+type Queue = Arc<Mutex<Pending>>;
+
+/// The calls waiting to be sent by each underlying client.
+static QUEUES: Lazy<DashMap<TypeId, Queue>> = Lazy::new(DashMap::new);
+
+/// A [`Client`] that sends calls made in the same tick to the server in one request, using
+/// the client `C`.
+///
+/// See the [module documentation](self) for details.
+pub struct BatchClient<C>(PhantomData<C>);
+
+impl<CustErr, C> Client<CustErr> for BatchClient<C>
+where
+    C: Client<CustErr> + 'static,
+    CustErr: Serialize + DeserializeOwned + Send + 'static,
+{
+    type Request = BatchRequest;
+    type Response = BatchResponse;
+
+    async fn send(req: Self::Request) -> Result<Self::Response, ServerFnError<CustErr>> {
+        let queue = QUEUES.entry(TypeId::of::<C>()).or_default().value().clone();
+        let (tx, rx) = oneshot::channel();
+        let batch = {
+            let mut pending = queue.lock().expect("batch queue lock poisoned");
+            pending.waiting.push((req, tx));
+            let batch = pending.batch.get_or_insert_with(|| {
+                let queue = Arc::clone(&queue);
+                async move {
+                    let waiting = {
+                        let mut pending = queue.lock().expect("batch queue lock poisoned");
+                        pending.batch = None;
+                        std::mem::take(&mut pending.waiting)
+                    };
+                    send_batch::<C, CustErr>(waiting).await;
+                }
+                .boxed()
+                .shared()
+            });
+            batch.clone()
+        };
+
+        // each call waits for the others in this tick, and then the first one to be polled
+        // again sends all of them. every call in the batch drives it, so it is still sent if
+        // any of them are dropped
+        YieldNow(false).await;
+        batch.await;
+        rx.await
+            .map_err(|_| ServerFnError::Request("the batch was cancelled".into()))?
+            .map_err(|e| decode_error(&e))
+    }
+
+    // WebSockets can't be batched
+    fn open_websocket(
+        path: &str,
+        input: MessageStream,
+    ) -> impl Future<Output = Result<MessageStream, ServerFnError<CustErr>>> + Send {
+        C::open_websocket(path, input)
+    }
+}
+
+async fn send_batch<C, CustErr>(mut waiting: Vec<Waiting>)
+where
+    C: Client<CustErr>,
+    CustErr: Serialize + DeserializeOwned,
+{
+    let mut batches = Vec::new();
+    while waiting.len() > MAX_BATCH_SIZE {
+        let rest = waiting.split_off(MAX_BATCH_SIZE);
+        batches.push(waiting);
+        waiting = rest;
+    }
+    batches.push(waiting);
+    futures::future::join_all(batches.into_iter().map(send_one_batch::<C, CustErr>)).await;
+}
+
+async fn send_one_batch<C, CustErr>(mut waiting: Vec<Waiting>)
+where
+    C: Client<CustErr>,
+    CustErr: Serialize + DeserializeOwned,
+{
+    if waiting.len() == 1 {
+        let (req, tx) = waiting.remove(0);
+        let res = async {
+            let res = C::send(req.into_client_req()?).await?;
+            BatchResponse::from_client_res(res).await
+        }
+        .await;
+        _ = tx.send(res.map_err(|e| encode_error(&e)));
+        return;
+    }
+
+    let (reqs, txs): (Vec<_>, Vec<_>) = waiting.into_iter().unzip();
+    let entries = reqs
+        .into_iter()
+        .map(BatchRequest::into_entry)
+        .collect::<Vec<_>>();
+    let results: Result<_, ServerFnError<CustErr>> = async {
+        let body = serde_json::to_string(&entries)
+            .map_err(|e| ServerFnError::Serialization(e.to_string()))?;
+        let req =
+            C::Request::try_new_post(batch_path(), "application/json", "application/json", body)?;
+        let res = C::send(req).await?;
+        let status = res.status();
+        let text = res.try_into_string().await?;
+        if status != 200 {
+            return Err(serde_json::from_str(&text).unwrap_or_else(|_| {
+                ServerFnError::Response(format!("batch failed with status {status}: {text}"))
+            }));
+        }
+        let results = serde_json::from_str::<Vec<BatchEntryResult>>(&text)
+            .map_err(|e| ServerFnError::Deserialization(e.to_string()))?;
+        if results.len() != txs.len() {
+            return Err(ServerFnError::Response(format!(
+                "sent {} calls in a batch, but received {} results",
+                txs.len(),
+                results.len()
+            )));
+        }
+        Ok(results)
+    }
+    .await;
+
+    match results {
+        Ok(results) => {
+            for (tx, result) in txs.into_iter().zip(results) {
+                _ = tx.send(
+                    BatchResponse::from_result::<CustErr>(result).map_err(|e| encode_error(&e)),
+                );
+            }
+        }
+        Err(e) => {
+            let e = encode_error(&e);
+            for tx in txs {
+                _ = tx.send(Err(e.clone()));
+            }
+        }
+    }
+}
+
+/// Encodes an error as JSON, so that it can be passed to every call in a batch whatever its
+/// error type.
+fn encode_error<CustErr: Serialize>(err: &ServerFnError<CustErr>) -> String {
+    serde_json::to_string(err).unwrap_or_else(|e| {
+        let err = ServerFnError::<NoCustomError>::Serialization(e.to_string());
+        serde_json::to_string(&err).expect("built-in errors can be serialized")
+    })
+}
+
+fn decode_error<CustErr: DeserializeOwned>(err: &str) -> ServerFnError<CustErr> {
+    serde_json::from_str(err).unwrap_or_else(|e| ServerFnError::Deserialization(e.to_string()))
+}
+
+/// Lets any other calls that are ready in this tick run before the batch is sent.
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            Poll::Ready(())
+        } else {
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
@@ -1,4 +1,7 @@
-use crate::{error::ServerFnError, request::ClientReq, response::ClientRes};
+use crate::{
+    codec::MessageStream, error::ServerFnError, request::ClientReq, response::ClientRes,
+};
+use serde::de::DeserializeOwned;
 use std::future::Future;
 
 pub trait Client<CustErr> {
@@ -8,15 +11,41 @@ pub trait Client<CustErr> {
     fn send(
         req: Self::Request,
     ) -> impl Future<Output = Result<Self::Response, ServerFnError<CustErr>>> + Send;
+
+    /// Opens a WebSocket to the server function at `path`, sends it the messages from
+    /// `input`, and returns the messages that it sends back.
+    ///
+    /// If the server rejects the connection, this returns its error.
+    fn open_websocket(
+        path: &str,
+        input: MessageStream,
+    ) -> impl Future<Output = Result<MessageStream, ServerFnError<CustErr>>> + Send
+    where
+        CustErr: DeserializeOwned,
+    {
+        let _ = input;
+        let path = path.to_string();
+        async move {
+            Err(ServerFnError::Request(format!(
+                "this client can't open a WebSocket to {path}"
+            )))
+        }
+    }
 }
 
 #[cfg(feature = "browser")]
 pub mod browser {
     use super::Client;
     use crate::{
-        error::ServerFnError, request::browser::BrowserRequest, response::browser::BrowserResponse,
+        codec::{close_error, received_messages, MessageStream, Received, WebsocketMessage},
+        error::ServerFnError,
+        request::browser::BrowserRequest,
+        response::browser::BrowserResponse,
     };
+    use futures::{SinkExt, StreamExt};
+    use gloo_net::websocket::{futures::WebSocket, Message, WebSocketError};
     use send_wrapper::SendWrapper;
+    use serde::de::DeserializeOwned;
     use std::future::Future;
 
     pub struct BrowserClient;
@@ -37,16 +66,119 @@ pub mod browser {
                     .map_err(|e| ServerFnError::Request(e.to_string()))
             })
         }
+
+        fn open_websocket(
+            path: &str,
+            mut input: MessageStream,
+        ) -> impl Future<Output = Result<MessageStream, ServerFnError<CustErr>>> + Send
+        where
+            CustErr: DeserializeOwned,
+        {
+            let url = websocket_url(path);
+            // the browser is single-threaded, so this can't move between threads
+            SendWrapper::new(async move {
+                let socket = WebSocket::open(&url?)
+                    .map_err(|e| ServerFnError::Request(e.to_string()))?;
+                let (mut sink, stream) = socket.split();
+                wasm_bindgen_futures::spawn_local(async move {
+                    while let Some(Ok(msg)) = input.next().await {
+                        let msg = match msg {
+                            WebsocketMessage::Binary(data) => Message::Bytes(data.into()),
+                            WebsocketMessage::Text(text) => Message::Text(text),
+                        };
+                        if sink.send(msg).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+                let received = stream.map(|msg| match msg {
+                    Ok(Message::Bytes(data)) => Received::Message(WebsocketMessage::Binary(data.into())),
+                    Ok(Message::Text(text)) => Received::Message(WebsocketMessage::Text(text)),
+                    Err(WebSocketError::ConnectionClose(event)) => {
+                        Received::Closed(close_error(event.code, &event.reason))
+                    }
+                    Err(e) => Received::Closed(Some(ServerFnError::Request(e.to_string()))),
+                });
+                Ok(received_messages(SendWrapper::new(received)))
+            })
+        }
+    }
+
+    /// The absolute `ws:` or `wss:` URL of `path` on the current origin.
+    fn websocket_url<CustErr>(path: &str) -> Result<String, ServerFnError<CustErr>> {
+        let location = web_sys::window()
+            .ok_or_else(|| ServerFnError::Request("no window to open a WebSocket from".into()))?
+            .location();
+        let (protocol, host) = location
+            .protocol()
+            .and_then(|protocol| Ok((protocol, location.host()?)))
+            .map_err(|e| ServerFnError::Request(format!("{e:?}")))?;
+        let scheme = if protocol == "https:" { "wss" } else { "ws" };
+        Ok(format!("{scheme}://{host}{path}"))
+    }
+}
+
+/// A client that runs server functions in the same process, without sending any HTTP
+/// requests.
+///
+/// Each call is encoded into a request and decoded from the response, as usual, and runs
+/// through the same middleware, guards and body limits as a request to
+/// [`handle_server_fn`](crate::axum::handle_server_fn). This makes it useful for testing
+/// server functions end-to-end without binding a port, or for calling them while rendering
+/// on the server:
+///
+/// ```ignore
+/// #[server(client = LocalClient)]
+/// pub async fn add(a: i32, b: i32) -> Result<i32, ServerFnError> {
+///     Ok(a + b)
+/// }
+///
+/// #[tokio::test]
+/// async fn adds() {
+///     // on the server, `add(1, 2)` would run the body directly
+///     let sum = Add { a: 1, b: 2 }.run_on_client().await;
+///     assert_eq!(sum.unwrap(), 3);
+/// }
+/// ```
+///
+/// A call made from inside another server function can see the values in its
+/// [`ServerContext`](crate::context::ServerContext), but not its request. WebSocket server
+/// functions can't be called with this client.
+#[cfg(feature = "axum")]
+pub mod local {
+    use super::Client;
+    use crate::error::ServerFnError;
+    use axum::body::Body;
+    use http::{Request, Response};
+
+    pub struct LocalClient;
+
+    impl<CustErr> Client<CustErr> for LocalClient {
+        type Request = Request<Body>;
+        type Response = Response<Body>;
+
+        async fn send(req: Self::Request) -> Result<Self::Response, ServerFnError<CustErr>> {
+            let path = req.uri().path().to_string();
+            crate::axum::dispatch(req).await.ok_or_else(|| {
+                ServerFnError::Registration(format!("no server function at {path}"))
+            })
+        }
     }
 }
 
 #[cfg(feature = "reqwest")]
 pub mod reqwest {
     use super::Client;
-    use crate::{error::ServerFnError, request::reqwest::CLIENT};
-    use futures::TryFutureExt;
+    use crate::{
+        codec::{close_error, received_messages, MessageStream, Received, WebsocketMessage},
+        error::ServerFnError,
+        request::reqwest::{get_server_url, CLIENT},
+    };
+    use futures::{SinkExt, StreamExt, TryFutureExt};
     use reqwest::{Request, Response};
-    use std::future::Future;
+    use serde::de::DeserializeOwned;
+    use std::future::{ready, Future};
+    use tokio_tungstenite::tungstenite::{self, Message};
 
     pub struct ReqwestClient;
 
@@ -61,5 +193,68 @@ pub mod reqwest {
                 .execute(req)
                 .map_err(|e| ServerFnError::Request(e.to_string()))
         }
+
+        fn open_websocket(
+            path: &str,
+            mut input: MessageStream,
+        ) -> impl Future<Output = Result<MessageStream, ServerFnError<CustErr>>> + Send
+        where
+            CustErr: DeserializeOwned,
+        {
+            // http: becomes ws:, and https: becomes wss:
+            let url = format!("ws{}{path}", get_server_url().trim_start_matches("http"));
+            async move {
+                let (socket, _) = tokio_tungstenite::connect_async(url)
+                    .await
+                    .map_err(handshake_error)?;
+                let (mut sink, stream) = socket.split();
+                tokio::spawn(async move {
+                    while let Some(Ok(msg)) = input.next().await {
+                        let msg = match msg {
+                            WebsocketMessage::Binary(data) => Message::Binary(data.into()),
+                            WebsocketMessage::Text(text) => Message::Text(text),
+                        };
+                        if sink.send(msg).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+                let received = stream.filter_map(|msg| {
+                    ready(match msg {
+                        Ok(Message::Binary(data)) => {
+                            Some(Received::Message(WebsocketMessage::Binary(data.into())))
+                        }
+                        Ok(Message::Text(text)) => Some(Received::Message(WebsocketMessage::Text(text))),
+                        Ok(Message::Close(frame)) => Some(Received::Closed(
+                            frame.and_then(|frame| close_error(frame.code.into(), &frame.reason)),
+                        )),
+                        // pings are answered by tungstenite
+                        Ok(Message::Ping(_) | Message::Pong(_) | Message::Frame(_)) => None,
+                        Err(e) => Some(Received::Closed(Some(ServerFnError::Request(e.to_string())))),
+                    })
+                });
+                Ok(received_messages(received))
+            }
+        }
+    }
+
+    /// Converts an error opening a WebSocket, which may be an error response from the server.
+    fn handshake_error<CustErr>(err: tungstenite::Error) -> ServerFnError<CustErr>
+    where
+        CustErr: DeserializeOwned,
+    {
+        match err {
+            tungstenite::Error::Http(res) => {
+                let status = res.status();
+                let body = res.into_body().unwrap_or_default();
+                serde_json::from_slice(&body).unwrap_or_else(|_| {
+                    ServerFnError::Request(format!(
+                        "{status}: {}",
+                        String::from_utf8_lossy(&body)
+                    ))
+                })
+            }
+            err => ServerFnError::Request(err.to_string()),
+        }
     }
 }
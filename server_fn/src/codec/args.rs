@@ -0,0 +1,421 @@
+//! Deserialization of server function arguments that reports which argument failed.
+
+use crate::error::ServerFnError;
+use serde::{
+    de::{
+        self, DeserializeSeed, EnumAccess, Expected, MapAccess, SeqAccess, Unexpected,
+        VariantAccess, Visitor,
+    },
+    Deserialize, Deserializer,
+};
+use serde_path_to_error::{Path, Track};
+use std::{
+    cell::{Cell, RefCell},
+    fmt::{self, Display},
+};
+
+thread_local! {
+    // set by `Tracked` when deserialization fails; deserializing is synchronous, so this is
+    // always read on the same thread that set it
+    static ERROR_PATH: RefCell<Option<Path>> = const { RefCell::new(None) };
+    // set by `Recorded` when a struct is missing a field, and cleared by any later error or
+    // by a value that deserializes after all, like another variant of an untagged enum
+    static MISSING_FIELD: Cell<Option<&'static str>> = const { Cell::new(None) };
+}
+
+/// Wraps the arguments so that any deserializer records the path to the field that failed.
+///
+/// This is used instead of `serde_path_to_error::deserialize` because not every format
+/// exposes its `Deserializer`.
+pub(crate) struct Tracked<T>(T);
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Tracked<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let mut track = Track::new();
+        let deserializer = serde_path_to_error::Deserializer::new(deserializer, &mut track);
+        match T::deserialize(Recording(deserializer)) {
+            Ok(value) => Ok(Tracked(value)),
+            Err(Recorded(e)) => {
+                ERROR_PATH.with(|path| *path.borrow_mut() = Some(track.path()));
+                Err(e)
+            }
+        }
+    }
+}
+
+/// An error of the format that the arguments are decoded from, which records the name of
+/// the field when it is raised by [`de::Error::missing_field`].
+///
+/// Derived `Deserialize` implementations raise this error through the [`MapAccess`] that
+/// they read the struct from, so it only works for errors raised inside [`Recording`].
+#[derive(Debug)]
+struct Recorded<E>(E);
+
+impl<E: Display> Display for Recorded<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<E: de::Error> std::error::Error for Recorded<E> {}
+
+impl<E: de::Error> de::Error for Recorded<E> {
+    fn custom<T: Display>(msg: T) -> Self {
+        MISSING_FIELD.with(|missing| missing.set(None));
+        Recorded(E::custom(msg))
+    }
+
+    fn invalid_type(unexp: Unexpected, exp: &dyn Expected) -> Self {
+        MISSING_FIELD.with(|missing| missing.set(None));
+        Recorded(E::invalid_type(unexp, exp))
+    }
+
+    fn invalid_value(unexp: Unexpected, exp: &dyn Expected) -> Self {
+        MISSING_FIELD.with(|missing| missing.set(None));
+        Recorded(E::invalid_value(unexp, exp))
+    }
+
+    fn invalid_length(len: usize, exp: &dyn Expected) -> Self {
+        MISSING_FIELD.with(|missing| missing.set(None));
+        Recorded(E::invalid_length(len, exp))
+    }
+
+    fn unknown_variant(variant: &str, expected: &'static [&'static str]) -> Self {
+        MISSING_FIELD.with(|missing| missing.set(None));
+        Recorded(E::unknown_variant(variant, expected))
+    }
+
+    fn unknown_field(field: &str, expected: &'static [&'static str]) -> Self {
+        MISSING_FIELD.with(|missing| missing.set(None));
+        Recorded(E::unknown_field(field, expected))
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        MISSING_FIELD.with(|missing| missing.set(Some(field)));
+        Recorded(E::missing_field(field))
+    }
+
+    fn duplicate_field(field: &'static str) -> Self {
+        MISSING_FIELD.with(|missing| missing.set(None));
+        Recorded(E::duplicate_field(field))
+    }
+}
+
+/// Wraps a deserializer, and everything that it hands to the arguments while they are
+/// deserialized, so that their errors are raised as [`Recorded`].
+struct Recording<T>(T);
+
+macro_rules! forward_deserialize {
+    ($($method:ident($($arg:ident: $ty:ty),*);)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(
+                self,
+                $($arg: $ty,)*
+                visitor: V,
+            ) -> Result<V::Value, Self::Error> {
+                self.0.$method($($arg,)* Recording(visitor)).map_err(Recorded)
+            }
+        )*
+    };
+}
+
+impl<'de, D: Deserializer<'de>> Deserializer<'de> for Recording<D> {
+    type Error = Recorded<D::Error>;
+
+    forward_deserialize! {
+        deserialize_any();
+        deserialize_bool();
+        deserialize_i8();
+        deserialize_i16();
+        deserialize_i32();
+        deserialize_i64();
+        deserialize_i128();
+        deserialize_u8();
+        deserialize_u16();
+        deserialize_u32();
+        deserialize_u64();
+        deserialize_u128();
+        deserialize_f32();
+        deserialize_f64();
+        deserialize_char();
+        deserialize_str();
+        deserialize_string();
+        deserialize_bytes();
+        deserialize_byte_buf();
+        deserialize_option();
+        deserialize_unit();
+        deserialize_unit_struct(name: &'static str);
+        deserialize_newtype_struct(name: &'static str);
+        deserialize_seq();
+        deserialize_tuple(len: usize);
+        deserialize_tuple_struct(name: &'static str, len: usize);
+        deserialize_map();
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+        deserialize_identifier();
+        deserialize_ignored_any();
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.0.is_human_readable()
+    }
+}
+
+macro_rules! forward_visit {
+    ($($method:ident($ty:ty);)*) => {
+        $(
+            fn $method<E: de::Error>(self, v: $ty) -> Result<Self::Value, E> {
+                self.0.$method(v)
+            }
+        )*
+    };
+}
+
+impl<'de, V: Visitor<'de>> Visitor<'de> for Recording<V> {
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.expecting(f)
+    }
+
+    forward_visit! {
+        visit_bool(bool);
+        visit_i8(i8);
+        visit_i16(i16);
+        visit_i32(i32);
+        visit_i64(i64);
+        visit_i128(i128);
+        visit_u8(u8);
+        visit_u16(u16);
+        visit_u32(u32);
+        visit_u64(u64);
+        visit_u128(u128);
+        visit_f32(f32);
+        visit_f64(f64);
+        visit_char(char);
+        visit_str(&str);
+        visit_borrowed_str(&'de str);
+        visit_string(String);
+        visit_bytes(&[u8]);
+        visit_borrowed_bytes(&'de [u8]);
+        visit_byte_buf(Vec<u8>);
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        self.0.visit_none()
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        self.0.visit_unit()
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        self.0.visit_some(Recording(deserializer)).map_err(|e| e.0)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error> {
+        self.0
+            .visit_newtype_struct(Recording(deserializer))
+            .map_err(|e| e.0)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+        self.0.visit_seq(Recording(seq)).map_err(|e| e.0)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        self.0.visit_map(Recording(map)).map_err(|e| e.0)
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Self::Value, A::Error> {
+        self.0.visit_enum(Recording(data)).map_err(|e| e.0)
+    }
+}
+
+impl<'de, S: DeserializeSeed<'de>> DeserializeSeed<'de> for Recording<S> {
+    type Value = S::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<S::Value, D::Error> {
+        let value = self.0.deserialize(Recording(deserializer)).map_err(|e| e.0)?;
+        // anything missing was inside a value that deserialized after all
+        MISSING_FIELD.with(|missing| missing.set(None));
+        Ok(value)
+    }
+}
+
+impl<'de, A: SeqAccess<'de>> SeqAccess<'de> for Recording<A> {
+    type Error = Recorded<A::Error>;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        self.0.next_element_seed(Recording(seed)).map_err(Recorded)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.0.size_hint()
+    }
+}
+
+impl<'de, A: MapAccess<'de>> MapAccess<'de> for Recording<A> {
+    type Error = Recorded<A::Error>;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        self.0.next_key_seed(Recording(seed)).map_err(Recorded)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.0.next_value_seed(Recording(seed)).map_err(Recorded)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.0.size_hint()
+    }
+}
+
+impl<'de, A: EnumAccess<'de>> EnumAccess<'de> for Recording<A> {
+    type Error = Recorded<A::Error>;
+    type Variant = Recording<A::Variant>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), Self::Error> {
+        self.0
+            .variant_seed(Recording(seed))
+            .map(|(value, variant)| (value, Recording(variant)))
+            .map_err(Recorded)
+    }
+}
+
+impl<'de, A: VariantAccess<'de>> VariantAccess<'de> for Recording<A> {
+    type Error = Recorded<A::Error>;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        self.0.unit_variant().map_err(Recorded)
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, Self::Error> {
+        self.0
+            .newtype_variant_seed(Recording(seed))
+            .map_err(Recorded)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.0
+            .tuple_variant(len, Recording(visitor))
+            .map_err(Recorded)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.0
+            .struct_variant(fields, Recording(visitor))
+            .map_err(Recorded)
+    }
+}
+
+/// The error of a format that arguments can be decoded from.
+pub(crate) trait ArgsError: Display {
+    /// The message of an error raised while deserializing the arguments themselves, like a
+    /// missing field or a value of the wrong type, without anything the format adds to it.
+    /// Returns `None` for errors in the format itself, like invalid syntax.
+    fn data_message(&self) -> Option<String>;
+}
+
+impl ArgsError for serde_json::Error {
+    fn data_message(&self) -> Option<String> {
+        if !self.is_data() {
+            return None;
+        }
+        // the message is followed by the position, unless there is none
+        let message = self.to_string();
+        let position = format!(" at line {} column {}", self.line(), self.column());
+        Some(match message.strip_suffix(&position) {
+            Some(message) => message.to_string(),
+            None => message,
+        })
+    }
+}
+
+#[cfg(feature = "url")]
+impl ArgsError for serde_qs::Error {
+    fn data_message(&self) -> Option<String> {
+        match self {
+            serde_qs::Error::Custom(message) => Some(message.clone()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "cbor")]
+impl<T: std::fmt::Debug> ArgsError for ciborium::de::Error<T> {
+    fn data_message(&self) -> Option<String> {
+        match self {
+            ciborium::de::Error::Semantic(_, message) => Some(message.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Runs a deserializer on the arguments, turning any error into [`ServerFnError::MissingArg`]
+/// or [`ServerFnError::Args`] with the path of the field that failed.
+pub(crate) fn deserialize_args<T, E: ArgsError, CustErr>(
+    deserialize: impl FnOnce() -> Result<Tracked<T>, E>,
+) -> Result<T, ServerFnError<CustErr>> {
+    ERROR_PATH.with(|path| path.borrow_mut().take());
+    MISSING_FIELD.with(|missing| missing.set(None));
+    match deserialize() {
+        Ok(Tracked(value)) => Ok(value),
+        Err(e) => {
+            let path = ERROR_PATH
+                .with(|path| path.borrow_mut().take())
+                .map(|path| path.to_string())
+                // the root, or a path made up of only unknown segments
+                .filter(|path| path != "." && path.chars().any(|c| c != '?' && c != '.'));
+            let missing = MISSING_FIELD.with(Cell::take);
+            Err(args_error(path, missing, &e))
+        }
+    }
+}
+
+fn args_error<CustErr>(
+    path: Option<String>,
+    missing: Option<&'static str>,
+    error: &impl ArgsError,
+) -> ServerFnError<CustErr> {
+    // serde reports missing fields of the struct that contains them, so the path is the
+    // path of that struct
+    let message = error
+        .data_message()
+        .unwrap_or_else(|| error.to_string());
+    match (missing, path) {
+        (Some(field), Some(path)) => ServerFnError::MissingArg(format!("{path}.{field}")),
+        (Some(field), None) => ServerFnError::MissingArg(field.to_string()),
+        (None, Some(path)) => ServerFnError::Args(format!("{path}: {message}")),
+        (None, None) => ServerFnError::Args(message),
+    }
+}
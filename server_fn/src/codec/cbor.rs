@@ -1,3 +1,4 @@
+use super::args::deserialize_args;
 use super::{Encoding, FromReq, FromRes, IntoReq, IntoRes};
 use crate::error::ServerFnError;
 use crate::request::{ClientReq, Req};
@@ -33,8 +34,7 @@ where
 {
     async fn from_req(req: Request) -> Result<Self, ServerFnError<CustErr>> {
         let body_bytes = req.try_into_bytes().await?;
-        ciborium::de::from_reader(body_bytes.as_ref())
-            .map_err(|e| ServerFnError::Args(e.to_string()))
+        deserialize_args(|| ciborium::de::from_reader(body_bytes.as_ref()))
     }
 }
 
@@ -1,3 +1,4 @@
+use super::args::deserialize_args;
 use super::{Encoding, FromReq, FromRes};
 use crate::error::ServerFnError;
 use crate::request::{ClientReq, Req};
@@ -31,7 +32,7 @@ where
 {
     async fn from_req(req: Request) -> Result<Self, ServerFnError<CustErr>> {
         let string_data = req.try_into_string().await?;
-        serde_json::from_str::<Self>(&string_data).map_err(|e| ServerFnError::Args(e.to_string()))
+        deserialize_args(|| serde_json::from_str(&string_data))
     }
 }
 
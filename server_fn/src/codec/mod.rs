@@ -1,3 +1,5 @@
+#[cfg(any(feature = "json", feature = "url", feature = "cbor"))]
+mod args;
 #[cfg(feature = "cbor")]
 mod cbor;
 #[cfg(feature = "cbor")]
@@ -25,6 +27,9 @@ pub use multipart::*;
 mod stream;
 pub use stream::*;
 
+mod websocket;
+pub use websocket::*;
+
 pub trait FromReq<CustErr, Request, Encoding>
 where
     Self: Sized,
@@ -50,4 +55,7 @@ pub trait IntoRes<CustErr, Response, Encoding> {
 
 pub trait Encoding {
     const CONTENT_TYPE: &'static str;
+
+    /// The HTTP method of requests that use this encoding.
+    const METHOD: &'static str = "POST";
 }
@@ -1,4 +1,7 @@
-use std::pin::Pin;
+use std::{
+    fmt::{self, Debug, Formatter},
+    pin::Pin,
+};
 
 use super::{Encoding, FromRes};
 use crate::error::{NoCustomError, ServerFnError};
@@ -34,10 +37,23 @@ where
 } */
 
 pub struct ByteStream<CustErr = NoCustomError>(
-    Pin<Box<dyn Stream<Item = Result<Bytes, ServerFnError<CustErr>>> + Send>>,
+    pub(crate) Pin<Box<dyn Stream<Item = Result<Bytes, ServerFnError<CustErr>>> + Send>>,
 );
 
+impl<CustErr> Debug for ByteStream<CustErr> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("ByteStream(..)")
+    }
+}
+
 impl<CustErr> ByteStream<CustErr> {
+    /// Creates a stream that can also yield errors, unlike the `From` implementation.
+    pub fn new(
+        stream: impl Stream<Item = Result<Bytes, ServerFnError<CustErr>>> + Send + 'static,
+    ) -> Self {
+        Self(Box::pin(stream))
+    }
+
     pub fn into_inner(self) -> impl Stream<Item = Result<Bytes, ServerFnError<CustErr>>> + Send {
         self.0
     }
@@ -80,10 +96,23 @@ impl Encoding for StreamingText {
 }
 
 pub struct TextStream<CustErr = NoCustomError>(
-    Pin<Box<dyn Stream<Item = Result<String, ServerFnError<CustErr>>> + Send>>,
+    pub(crate) Pin<Box<dyn Stream<Item = Result<String, ServerFnError<CustErr>>> + Send>>,
 );
 
+impl<CustErr> Debug for TextStream<CustErr> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("TextStream(..)")
+    }
+}
+
 impl<CustErr> TextStream<CustErr> {
+    /// Creates a stream that can also yield errors, unlike the `From` implementation.
+    pub fn new(
+        stream: impl Stream<Item = Result<String, ServerFnError<CustErr>>> + Send + 'static,
+    ) -> Self {
+        Self(Box::pin(stream))
+    }
+
     pub fn into_inner(self) -> impl Stream<Item = Result<String, ServerFnError<CustErr>>> + Send {
         self.0
     }
@@ -1,3 +1,4 @@
+use super::args::deserialize_args;
 use super::{Encoding, FromReq, IntoReq};
 use crate::error::ServerFnError;
 use crate::request::{ClientReq, Req};
@@ -12,6 +13,7 @@ pub struct PostUrl;
 
 impl Encoding for GetUrl {
     const CONTENT_TYPE: &'static str = "application/x-www-form-urlencoded";
+    const METHOD: &'static str = "GET";
 }
 
 impl<CustErr, T, Request> IntoReq<CustErr, Request, GetUrl> for T
@@ -33,9 +35,7 @@ where
 {
     async fn from_req(req: Request) -> Result<Self, ServerFnError<CustErr>> {
         let string_data = req.as_query().unwrap_or_default();
-        let args = serde_qs::from_str::<Self>(string_data)
-            .map_err(|e| ServerFnError::Args(e.to_string()))?;
-        Ok(args)
+        deserialize_args(|| serde_qs::from_str(string_data))
     }
 }
 
@@ -62,9 +62,7 @@ where
 {
     async fn from_req(req: Request) -> Result<Self, ServerFnError<CustErr>> {
         let string_data = req.try_into_string().await?;
-        let args = serde_qs::from_str::<Self>(&string_data)
-            .map_err(|e| ServerFnError::Args(e.to_string()))?;
-        Ok(args)
+        deserialize_args(|| serde_qs::from_str(&string_data))
     }
 }
 
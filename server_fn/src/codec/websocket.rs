@@ -0,0 +1,306 @@
+//! Server functions whose input and output are both streams, carried over a WebSocket.
+//!
+//! A server function with the [`Websocket`] input and output encodings takes a [`ByteStream`]
+//! of the messages sent by the client, and returns a [`ByteStream`] of the messages to send
+//! back, while both sides keep sending for as long as they need to. [`WebsocketText`] does
+//! the same with a [`TextStream`] of text messages. The two can be mixed, so that a server
+//! function receives text and sends binary messages, for example.
+//!
+//! ```ignore
+//! #[server(input = WebsocketText, output = WebsocketText)]
+//! pub async fn chat(input: TextStream) -> Result<TextStream, ServerFnError> {
+//!     Ok(TextStream::new(
+//!         input
+//!             .into_inner()
+//!             .map(|msg| msg.map(|msg| format!("echo: {msg}"))),
+//!     ))
+//! }
+//!
+//! // on the client
+//! let (tx, rx) = futures::channel::mpsc::unbounded::<String>();
+//! let mut replies = chat(rx.into()).await?.into_inner();
+//! tx.unbounded_send("hello".into())?;
+//! let reply = replies.next().await; // Some(Ok("echo: hello"))
+//! ```
+//!
+//! The request upgrades to a WebSocket at the server function's path, after its guards and
+//! middleware have run, so it can be rejected with an error like any other call. The
+//! connection is only opened once the server function has returned its output stream, so
+//! it should not wait for any input before returning.
+//!
+//! If the output stream (created with [`TextStream::new`] or [`ByteStream::new`]) yields an
+//! error, the connection is closed and the client's stream yields the same error. Either side
+//! can stop sending without closing the connection: it is closed when the server's output
+//! stream ends, or once the client has dropped the stream that it received and its input has
+//! ended.
+//!
+//! This is supported by the `axum` integration, and by the browser and `reqwest` clients.
+
+use super::{ByteStream, Encoding, FromReq, FromRes, IntoReq, IntoRes, TextStream};
+use crate::{
+    client::Client,
+    context::{provide_context, use_context},
+    error::ServerFnError,
+    request::{ClientReq, Req},
+    response::{ClientRes, Res},
+    ServerFn,
+};
+use bytes::Bytes;
+use futures::{channel::oneshot, Future, Stream, StreamExt};
+use std::{
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+
+/// Binary WebSocket messages, as a [`ByteStream`].
+pub struct Websocket;
+
+impl Encoding for Websocket {
+    const CONTENT_TYPE: &'static str = "application/octet-stream";
+    const METHOD: &'static str = "GET";
+}
+
+/// Text WebSocket messages, as a [`TextStream`].
+pub struct WebsocketText;
+
+impl Encoding for WebsocketText {
+    const CONTENT_TYPE: &'static str = "text/plain";
+    const METHOD: &'static str = "GET";
+}
+
+/// A message sent over the WebSocket of a server function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebsocketMessage {
+    Binary(Bytes),
+    Text(String),
+}
+
+/// The messages sent in one direction over a WebSocket.
+///
+/// An error ends the stream. When it is sent by the server, it closes the connection.
+pub type MessageStream = Pin<Box<dyn Stream<Item = Result<WebsocketMessage, ServerFnError>> + Send>>;
+
+/// The close code used when the server's output stream yields an error.
+#[cfg(any(feature = "axum", feature = "browser", feature = "reqwest"))]
+pub(crate) const ERROR_CLOSE_CODE: u16 = 1011;
+
+/// The longest reason that can be given in a close frame, in bytes.
+#[cfg(feature = "axum")]
+const MAX_CLOSE_REASON: usize = 123;
+
+/// The reason given when closing a connection because of `err`: the error as JSON, if it
+/// fits.
+#[cfg(feature = "axum")]
+pub(crate) fn close_reason(err: &ServerFnError) -> String {
+    let json = serde_json::to_string(err).unwrap_or_default();
+    if json.len() <= MAX_CLOSE_REASON {
+        return json;
+    }
+    let mut message = err.to_string();
+    while message.len() > MAX_CLOSE_REASON {
+        message.pop();
+    }
+    message
+}
+
+/// The error that a connection was closed with, if any.
+#[cfg(any(feature = "axum", feature = "browser", feature = "reqwest"))]
+pub(crate) fn close_error(code: u16, reason: &str) -> Option<ServerFnError> {
+    (code == ERROR_CLOSE_CODE).then(|| {
+        serde_json::from_str(reason).unwrap_or_else(|_| ServerFnError::ServerError(reason.into()))
+    })
+}
+
+/// A message received by one of the WebSocket implementations.
+#[cfg(any(feature = "axum", feature = "browser", feature = "reqwest"))]
+pub(crate) enum Received {
+    Message(WebsocketMessage),
+    Closed(Option<ServerFnError>),
+}
+
+/// Ends the stream of received messages after the connection has closed.
+#[cfg(any(feature = "axum", feature = "browser", feature = "reqwest"))]
+pub(crate) fn received_messages(
+    received: impl Stream<Item = Received> + Send + 'static,
+) -> MessageStream {
+    Box::pin(futures::stream::unfold(
+        Some(Box::pin(received)),
+        |received| async move {
+            let mut received = received?;
+            match received.next().await? {
+                Received::Message(msg) => Some((Ok(msg), Some(received))),
+                Received::Closed(Some(err)) => Some((Err(err), None)),
+                Received::Closed(None) => None,
+            }
+        },
+    ))
+}
+
+/// Where a server function's output stream is sent, once it has returned it, to be sent
+/// over the WebSocket that its request was upgraded to.
+#[derive(Clone)]
+struct PendingOutput(Arc<Mutex<Option<oneshot::Sender<MessageStream>>>>);
+
+/// The stream type of a WebSocket encoding, and how it is sent as messages.
+pub trait WebsocketEncoding: Encoding {
+    type Stream;
+
+    fn into_messages(stream: Self::Stream) -> MessageStream;
+
+    fn from_messages(messages: MessageStream) -> Self::Stream;
+}
+
+impl WebsocketEncoding for Websocket {
+    type Stream = ByteStream;
+
+    fn into_messages(stream: ByteStream) -> MessageStream {
+        Box::pin(stream.into_inner().map(|chunk| chunk.map(WebsocketMessage::Binary)))
+    }
+
+    fn from_messages(messages: MessageStream) -> ByteStream {
+        ByteStream(Box::pin(messages.map(|msg| {
+            msg.map(|msg| match msg {
+                WebsocketMessage::Binary(data) => data,
+                WebsocketMessage::Text(text) => Bytes::from(text),
+            })
+        })))
+    }
+}
+
+impl WebsocketEncoding for WebsocketText {
+    type Stream = TextStream;
+
+    fn into_messages(stream: TextStream) -> MessageStream {
+        Box::pin(stream.into_inner().map(|chunk| chunk.map(WebsocketMessage::Text)))
+    }
+
+    fn from_messages(messages: MessageStream) -> TextStream {
+        TextStream(Box::pin(messages.map(|msg| {
+            msg.and_then(|msg| match msg {
+                WebsocketMessage::Text(text) => Ok(text),
+                WebsocketMessage::Binary(data) => String::from_utf8(data.into())
+                    .map_err(|e| ServerFnError::Deserialization(e.to_string())),
+            })
+        })))
+    }
+}
+
+async fn upgrade<CustErr, Request>(req: Request) -> Result<MessageStream, ServerFnError<CustErr>>
+where
+    Request: Req<CustErr>,
+{
+    let (input, output) = req.try_into_websocket().await?;
+    provide_context(PendingOutput(Arc::new(Mutex::new(Some(output)))));
+    Ok(input)
+}
+
+async fn send_output<CustErr, Response>(
+    output: MessageStream,
+) -> Result<Response, ServerFnError<CustErr>>
+where
+    Response: Res<CustErr>,
+{
+    let sender = use_context::<PendingOutput>()
+        .and_then(|pending| pending.0.lock().expect("WebSocket lock poisoned").take())
+        .ok_or_else(|| {
+            ServerFnError::Response(
+                "a WebSocket output needs a WebSocket input, to upgrade the request".into(),
+            )
+        })?;
+    // if the connection has already closed, there's nothing to send it to
+    _ = sender.send(output);
+    // the status and headers that complete the upgrade are set in the `ResponseOptions`
+    Response::try_from_bytes(Websocket::CONTENT_TYPE, Bytes::new())
+}
+
+impl<CustErr, T, Request> FromReq<CustErr, Request, Websocket> for T
+where
+    Request: Req<CustErr> + Send + 'static,
+    T: From<ByteStream>,
+{
+    async fn from_req(req: Request) -> Result<Self, ServerFnError<CustErr>> {
+        Ok(Websocket::from_messages(upgrade(req).await?).into())
+    }
+}
+
+impl<CustErr, T, Request> FromReq<CustErr, Request, WebsocketText> for T
+where
+    Request: Req<CustErr> + Send + 'static,
+    T: From<TextStream>,
+{
+    async fn from_req(req: Request) -> Result<Self, ServerFnError<CustErr>> {
+        Ok(WebsocketText::from_messages(upgrade(req).await?).into())
+    }
+}
+
+impl<CustErr, Response> IntoRes<CustErr, Response, Websocket> for ByteStream
+where
+    Response: Res<CustErr>,
+{
+    async fn into_res(self) -> Result<Response, ServerFnError<CustErr>> {
+        send_output(Websocket::into_messages(self)).await
+    }
+}
+
+impl<CustErr, Response> IntoRes<CustErr, Response, WebsocketText> for TextStream
+where
+    Response: Res<CustErr>,
+{
+    async fn into_res(self) -> Result<Response, ServerFnError<CustErr>> {
+        send_output(WebsocketText::into_messages(self)).await
+    }
+}
+
+// WebSocket server functions are called with `run_websocket_on_client` below, rather than by sending an
+// HTTP request, but these are still needed to implement `ServerFn`
+macro_rules! unsupported_over_http {
+    ($encoding:ty, $stream:ty) => {
+        impl<CustErr, T, Request> IntoReq<CustErr, Request, $encoding> for T
+        where
+            Request: ClientReq<CustErr>,
+            T: Into<$stream>,
+        {
+            fn into_req(self, path: &str, _accepts: &str) -> Result<Request, ServerFnError<CustErr>> {
+                Err(ServerFnError::Request(format!(
+                    "{path} can only be called over a WebSocket"
+                )))
+            }
+        }
+
+        impl<CustErr, Response> FromRes<CustErr, Response, $encoding> for $stream
+        where
+            Response: ClientRes<CustErr> + Send,
+        {
+            async fn from_res(_res: Response) -> Result<Self, ServerFnError<CustErr>> {
+                Err(ServerFnError::Request(
+                    "WebSocket server functions can't be called over HTTP".into(),
+                ))
+            }
+        }
+    };
+}
+
+unsupported_over_http!(Websocket, ByteStream);
+unsupported_over_http!(WebsocketText, TextStream);
+
+/// Calls a WebSocket server function from the client, with
+/// [`Client::open_websocket`](crate::client::Client::open_websocket).
+///
+/// The `#[server]` macro uses this as the `run_on_client` of server functions with a
+/// WebSocket encoding.
+#[doc(hidden)]
+pub fn run_websocket_on_client<T>(
+    args: T,
+) -> impl Future<Output = Result<T::Output, ServerFnError<T::Error>>> + Send
+where
+    T: ServerFn + Into<<T::InputEncoding as WebsocketEncoding>::Stream>,
+    T::InputEncoding: WebsocketEncoding,
+    T::OutputEncoding: WebsocketEncoding,
+    T::Output: From<<T::OutputEncoding as WebsocketEncoding>::Stream>,
+{
+    let input = T::InputEncoding::into_messages(args.into());
+    async move {
+        let output = T::Client::open_websocket(T::PATH, input).await?;
+        Ok(T::OutputEncoding::from_messages(output).into())
+    }
+}
@@ -0,0 +1,117 @@
+//! Request-scoped context for server functions running on the server.
+//!
+//! Because the `#[server]` macro turns the arguments of a function into its serialized
+//! arguments struct, the body of the function has no direct access to the HTTP request.
+//! Instead, the request (and anything else the integration or your own handler wants to
+//! share) is stored in a [`ServerContext`] that is available while the body is running.
+
+use std::{
+    any::{Any, TypeId},
+    cell::RefCell,
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, RwLock},
+    task::{self, Poll},
+};
+
+thread_local! {
+    static CURRENT: RefCell<Option<ServerContext>> = const { RefCell::new(None) };
+}
+
+/// A map of values, keyed by type, that are available to a server function while it is
+/// running on the server.
+///
+/// A new context is created for each server function call. Any values in a context that was
+/// already active when the call began (see [`ServerContext::scope`]) are visible through it.
+#[derive(Clone, Default)]
+pub struct ServerContext {
+    values: Arc<RwLock<HashMap<TypeId, Box<dyn Any + Send + Sync>>>>,
+    parent: Option<Box<ServerContext>>,
+}
+
+impl ServerContext {
+    /// Creates a new, empty context.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new context that falls back to this one for any value it does not contain.
+    pub fn child(&self) -> Self {
+        Self {
+            values: Default::default(),
+            parent: Some(Box::new(self.clone())),
+        }
+    }
+
+    /// Returns the context in which the current server function is running, if any.
+    pub fn current() -> Option<Self> {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+
+    /// Adds a value to the context, replacing any existing value of the same type.
+    pub fn insert<T: Send + Sync + 'static>(&self, value: T) {
+        self.values
+            .write()
+            .expect("ServerContext lock poisoned")
+            .insert(TypeId::of::<T>(), Box::new(value));
+    }
+
+    /// Returns a clone of the value of type `T` in this context, if there is one.
+    pub fn get<T: Clone + 'static>(&self) -> Option<T> {
+        self.values
+            .read()
+            .expect("ServerContext lock poisoned")
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref::<T>())
+            .cloned()
+            .or_else(|| self.parent.as_ref().and_then(|parent| parent.get()))
+    }
+
+    /// Runs the future with this as the current context.
+    pub fn scope<F: Future>(self, fut: F) -> Scoped<F> {
+        Scoped {
+            context: self,
+            inner: Box::pin(fut),
+        }
+    }
+}
+
+/// A future that runs inside a [`ServerContext`], returned by [`ServerContext::scope`].
+pub struct Scoped<F> {
+    context: ServerContext,
+    inner: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for Scoped<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        struct Restore(Option<ServerContext>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                CURRENT.with(|current| *current.borrow_mut() = self.0.take());
+            }
+        }
+
+        let this = &mut *self;
+        let _restore = Restore(CURRENT.with(|current| current.replace(Some(this.context.clone()))));
+        this.inner.as_mut().poll(cx)
+    }
+}
+
+/// Adds a value to the context of the server function that is currently running.
+///
+/// This does nothing if it is called outside a server function.
+pub fn provide_context<T: Send + Sync + 'static>(value: T) {
+    if let Some(context) = ServerContext::current() {
+        context.insert(value);
+    }
+}
+
+/// Returns a clone of the value of type `T` in the context of the server function that is
+/// currently running, if there is one.
+pub fn use_context<T: Clone + 'static>() -> Option<T> {
+    ServerContext::current().and_then(|context| context.get())
+}
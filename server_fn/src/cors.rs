@@ -0,0 +1,168 @@
+//! Cross-origin resource sharing (CORS) for server functions.
+//!
+//! By default, browsers will not let a page on one origin call server functions on another.
+//! Setting a [`Cors`] configuration with `set_cors` in the server integration allows it:
+//! preflight `OPTIONS` requests for any registered server function are answered with that
+//! function's method, and the CORS headers are added to the responses of normal calls.
+//!
+//! ```ignore
+//! use server_fns::cors::Cors;
+//!
+//! server_fns::axum::set_cors(
+//!     Cors::new()
+//!         .allow_origin("https://app.example.com")
+//!         .allow_credentials(true),
+//! );
+//! ```
+
+use crate::{idempotency::IDEMPOTENCY_KEY_HEADER, redirect::REDIRECT_HEADER};
+use std::time::Duration;
+
+/// The request headers that the clients in this crate can send, which are always allowed.
+/// `traceparent` is sent by clients built with the `tracing` feature, which the server may
+/// be built without.
+const ALLOWED_HEADERS: [&str; 4] = [
+    "content-type",
+    "accept",
+    "traceparent",
+    IDEMPOTENCY_KEY_HEADER,
+];
+
+/// The CORS configuration for all server functions.
+#[derive(Debug, Clone, Default)]
+pub struct Cors {
+    any_origin: bool,
+    origins: Vec<String>,
+    credentials: bool,
+    headers: Vec<String>,
+    expose_headers: Vec<String>,
+    max_age: Option<Duration>,
+}
+
+impl Cors {
+    /// Creates a configuration that does not allow any origins yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows calls from the given origin, like `https://app.example.com`.
+    pub fn allow_origin(mut self, origin: impl Into<String>) -> Self {
+        let origin = origin.into();
+        self.origins.push(origin.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Allows calls from any origin.
+    ///
+    /// This can't be combined with [`allow_credentials`](Self::allow_credentials), since that
+    /// would let any site make calls with the user's cookies and read the responses.
+    pub fn allow_any_origin(mut self) -> Self {
+        self.any_origin = true;
+        self
+    }
+
+    /// Allows calls to include cookies and other credentials.
+    ///
+    /// This can only be used with origins allowed by [`allow_origin`](Self::allow_origin),
+    /// not with [`allow_any_origin`](Self::allow_any_origin).
+    pub fn allow_credentials(mut self, credentials: bool) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    /// Allows calls to set the given request header, in addition to the headers that the
+    /// clients in this crate send, like `Content-Type`.
+    pub fn allow_header(mut self, header: impl Into<String>) -> Self {
+        self.headers.push(header.into());
+        self
+    }
+
+    /// Allows the calling page to read the given response header. The headers used by
+    /// server functions themselves, like `Location`, are always exposed.
+    pub fn expose_header(mut self, header: impl Into<String>) -> Self {
+        self.expose_headers.push(header.into());
+        self
+    }
+
+    /// Sets how long the browser can cache the result of a preflight request.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Whether calls from the given origin are allowed.
+    pub fn allows(&self, origin: &str) -> bool {
+        self.any_origin || self.origins.iter().any(|allowed| allowed == origin)
+    }
+
+    /// Panics if the configuration allows credentials from any origin, like browsers and
+    /// `tower-http` do, rather than echoing every origin back.
+    pub(crate) fn assert_valid(&self) {
+        assert!(
+            !(self.any_origin && self.credentials),
+            "invalid CORS configuration: `allow_credentials(true)` can't be combined with \
+             `allow_any_origin()`, so allow each origin with `allow_origin` instead"
+        );
+    }
+
+    fn allow_origin_header(&self, origin: &str) -> String {
+        if self.any_origin {
+            "*".to_string()
+        } else {
+            origin.to_string()
+        }
+    }
+
+    /// The headers to add to the response of a normal call from the given origin.
+    pub fn response_headers(&self, origin: Option<&str>) -> Vec<(&'static str, String)> {
+        let mut headers = vec![("vary", "Origin".to_string())];
+        let Some(origin) = origin.filter(|origin| self.allows(origin)) else {
+            return headers;
+        };
+        headers.push((
+            "access-control-allow-origin",
+            self.allow_origin_header(origin),
+        ));
+        if self.credentials {
+            headers.push(("access-control-allow-credentials", "true".to_string()));
+        }
+        let expose = ["location", REDIRECT_HEADER, "retry-after"]
+            .into_iter()
+            .chain(self.expose_headers.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(", ");
+        headers.push(("access-control-expose-headers", expose));
+        headers
+    }
+
+    /// The headers to add to the response to a preflight request from the given origin, for
+    /// a server function that uses the given HTTP method.
+    pub fn preflight_headers(
+        &self,
+        origin: Option<&str>,
+        method: &str,
+    ) -> Vec<(&'static str, String)> {
+        let mut headers = vec![("vary", "Origin".to_string())];
+        let Some(origin) = origin.filter(|origin| self.allows(origin)) else {
+            return headers;
+        };
+        headers.push((
+            "access-control-allow-origin",
+            self.allow_origin_header(origin),
+        ));
+        if self.credentials {
+            headers.push(("access-control-allow-credentials", "true".to_string()));
+        }
+        headers.push(("access-control-allow-methods", method.to_string()));
+        let allow_headers = ALLOWED_HEADERS
+            .into_iter()
+            .chain(self.headers.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(", ");
+        headers.push(("access-control-allow-headers", allow_headers));
+        if let Some(max_age) = self.max_age {
+            headers.push(("access-control-max-age", max_age.as_secs().to_string()));
+        }
+        headers
+    }
+}
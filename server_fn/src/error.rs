@@ -1,5 +1,6 @@
 use core::fmt::{self, Display};
 
+use crate::validate::ValidationErrors;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
@@ -92,8 +93,12 @@ impl<E> ViaError<E> for WrapError<E> {
 /// Unlike [`ServerFnErrorErr`], this does not implement [`Error`](std::error::Error).
 /// This means that other error types can easily be converted into it using the
 /// `?` operator.
+///
+/// Error responses carry the error as JSON, with the name of the variant in `type` and its
+/// contents in `message`, like `{"type":"MissingArg","message":"name"}`, so that the
+/// client can turn it back into the same variant.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(tag = "type")]
+#[serde(tag = "type", content = "message")]
 pub enum ServerFnError<E = NoCustomError> {
     WrappedServerError(E),
     /// Error while trying to register the server function (only occurs in case of poisoned RwLock).
@@ -112,6 +117,77 @@ pub enum ServerFnError<E = NoCustomError> {
     Args(String),
     /// Occurs on the server if there's a missing argument.
     MissingArg(String),
+    /// Occurs on the server if the arguments fail validation.
+    Validation(ValidationErrors),
+    /// Occurs on the server if the client has made too many requests, with the number of
+    /// seconds to wait before trying again.
+    RateLimited(u64),
+    /// Occurs on the server if the request is not allowed to call the server function.
+    Forbidden(String),
+    /// Occurs on the server if the client has not authenticated.
+    Unauthorized(String),
+    /// Occurs on the server if the request body is larger than the limit, in bytes.
+    PayloadTooLarge(u64),
+    /// Occurs on the server if the server function is too busy to accept another call.
+    Unavailable(String),
+    /// Occurs on the server if the server function did not finish in time.
+    Timeout(String),
+    /// Occurs on the server if the request conflicts with an earlier one, like a call that
+    /// reuses an idempotency key with different arguments.
+    Conflict(String),
+    /// Occurs on the server if a middleware that needs to know which client made a call, like
+    /// rate limiting, can't tell from the request. This usually means that the app isn't
+    /// served the way that the middleware expects.
+    UnidentifiedClient(String),
+}
+
+impl<CustErr> ServerFnError<CustErr> {
+    /// The HTTP status code of the response when this error is returned by a server function.
+    pub fn status_code(&self) -> u16 {
+        match self {
+            ServerFnError::Validation(_) => 422,
+            ServerFnError::Unauthorized(_) => 401,
+            ServerFnError::Forbidden(_) => 403,
+            ServerFnError::RateLimited(_) => 429,
+            ServerFnError::PayloadTooLarge(_) => 413,
+            ServerFnError::Unavailable(_) => 503,
+            ServerFnError::Timeout(_) => 504,
+            ServerFnError::Conflict(_) => 409,
+            _ => 500,
+        }
+    }
+
+    /// The name of the kind of error, for logs and metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ServerFnError::WrappedServerError(_) => "WrappedServerError",
+            ServerFnError::Registration(_) => "Registration",
+            ServerFnError::Request(_) => "Request",
+            ServerFnError::Response(_) => "Response",
+            ServerFnError::ServerError(_) => "ServerError",
+            ServerFnError::Deserialization(_) => "Deserialization",
+            ServerFnError::Serialization(_) => "Serialization",
+            ServerFnError::Args(_) => "Args",
+            ServerFnError::MissingArg(_) => "MissingArg",
+            ServerFnError::Validation(_) => "Validation",
+            ServerFnError::RateLimited(_) => "RateLimited",
+            ServerFnError::Forbidden(_) => "Forbidden",
+            ServerFnError::Unauthorized(_) => "Unauthorized",
+            ServerFnError::PayloadTooLarge(_) => "PayloadTooLarge",
+            ServerFnError::Unavailable(_) => "Unavailable",
+            ServerFnError::Timeout(_) => "Timeout",
+            ServerFnError::Conflict(_) => "Conflict",
+            ServerFnError::UnidentifiedClient(_) => "UnidentifiedClient",
+        }
+    }
+
+    /// Whether the same call may succeed if it is made again later.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ServerFnError::RateLimited(_) | ServerFnError::Unavailable(_)
+        )
+    }
 }
 
 impl<CustErr> From<CustErr> for ServerFnError<CustErr> {
@@ -141,6 +217,17 @@ where
                 ServerFnError::Args(s) =>
                     format!("error deserializing server function arguments: {s}"),
                 ServerFnError::MissingArg(s) => format!("missing argument {s}"),
+                ServerFnError::Validation(e) => format!("invalid arguments: {e}"),
+                ServerFnError::RateLimited(s) => format!("too many requests, retry after {s}s"),
+                ServerFnError::Forbidden(s) => format!("forbidden: {s}"),
+                ServerFnError::Unauthorized(s) => format!("unauthorized: {s}"),
+                ServerFnError::PayloadTooLarge(s) =>
+                    format!("request body is larger than the limit of {s} bytes"),
+                ServerFnError::Unavailable(s) => format!("service unavailable: {s}"),
+                ServerFnError::Timeout(s) => format!("timed out: {s}"),
+                ServerFnError::Conflict(s) => format!("conflict: {s}"),
+                ServerFnError::UnidentifiedClient(s) =>
+                    format!("couldn't identify the client that made this call: {s}"),
                 ServerFnError::Response(s) => format!("error generating HTTP response: {s}"),
                 ServerFnError::WrappedServerError(e) => format!("{}", e),
             }
@@ -197,6 +284,33 @@ pub enum ServerFnErrorErr<E = NoCustomError> {
     /// Occurs on the server if there is an error creating an HTTP response.
     #[error("error creating response {0}")]
     Response(String),
+    /// Occurs on the server if the arguments fail validation.
+    #[error("invalid arguments: {0}")]
+    Validation(ValidationErrors),
+    /// Occurs on the server if the client has made too many requests.
+    #[error("too many requests, retry after {0}s")]
+    RateLimited(u64),
+    /// Occurs on the server if the request is not allowed to call the server function.
+    #[error("forbidden: {0}")]
+    Forbidden(String),
+    /// Occurs on the server if the client has not authenticated.
+    #[error("unauthorized: {0}")]
+    Unauthorized(String),
+    /// Occurs on the server if the request body is larger than the limit, in bytes.
+    #[error("request body is larger than the limit of {0} bytes")]
+    PayloadTooLarge(u64),
+    /// Occurs on the server if the server function is too busy to accept another call.
+    #[error("service unavailable: {0}")]
+    Unavailable(String),
+    /// Occurs on the server if the server function did not finish in time.
+    #[error("timed out: {0}")]
+    Timeout(String),
+    /// Occurs on the server if the request conflicts with an earlier one.
+    #[error("conflict: {0}")]
+    Conflict(String),
+    /// Occurs on the server if a middleware can't tell which client made a call.
+    #[error("couldn't identify the client that made this call: {0}")]
+    UnidentifiedClient(String),
 }
 
 impl<CustErr> From<ServerFnError<CustErr>> for ServerFnErrorErr<CustErr> {
@@ -211,6 +325,17 @@ impl<CustErr> From<ServerFnError<CustErr>> for ServerFnErrorErr<CustErr> {
             ServerFnError::MissingArg(value) => ServerFnErrorErr::MissingArg(value),
             ServerFnError::WrappedServerError(value) => ServerFnErrorErr::WrappedServerError(value),
             ServerFnError::Response(value) => ServerFnErrorErr::Response(value),
+            ServerFnError::Validation(value) => ServerFnErrorErr::Validation(value),
+            ServerFnError::RateLimited(value) => ServerFnErrorErr::RateLimited(value),
+            ServerFnError::Forbidden(value) => ServerFnErrorErr::Forbidden(value),
+            ServerFnError::Unauthorized(value) => ServerFnErrorErr::Unauthorized(value),
+            ServerFnError::PayloadTooLarge(value) => ServerFnErrorErr::PayloadTooLarge(value),
+            ServerFnError::Unavailable(value) => ServerFnErrorErr::Unavailable(value),
+            ServerFnError::Timeout(value) => ServerFnErrorErr::Timeout(value),
+            ServerFnError::Conflict(value) => ServerFnErrorErr::Conflict(value),
+            ServerFnError::UnidentifiedClient(value) => {
+                ServerFnErrorErr::UnidentifiedClient(value)
+            }
         }
     }
 }
@@ -0,0 +1,65 @@
+//! Guards that check whether a request may call a server function, before its arguments are
+//! decoded.
+//!
+//! A guard is added with `#[server(guard = ...)]`, where the expression evaluates to a
+//! function that takes a reference to the incoming request and returns a future that
+//! resolves to `Result<(), GuardRejection>`. The function runs synchronously, so it should
+//! copy anything it needs out of the request before returning the future. Both run inside
+//! the [`ServerContext`](crate::context::ServerContext) of the call, so the future can also
+//! use [`use_context`](crate::context::use_context) or an integration's `extract()`.
+//!
+//! ```ignore
+//! use server_fns::{guard::GuardRejection, request::Req, error::NoCustomError};
+//!
+//! fn require_role<R: Req<NoCustomError>>(
+//!     role: &'static str,
+//! ) -> impl Fn(&R) -> std::future::Ready<Result<(), GuardRejection>> {
+//!     move |req| {
+//!         std::future::ready(match req.header("x-role") {
+//!             None => Err(GuardRejection::Unauthorized("not logged in".into())),
+//!             Some(r) if r == role => Ok(()),
+//!             Some(_) => Err(GuardRejection::Forbidden(format!("requires role {role}"))),
+//!         })
+//!     }
+//! }
+//!
+//! #[server(guard = require_role("admin"))]
+//! pub async fn delete_user(id: u32) -> Result<(), ServerFnError> {
+//!     todo!()
+//! }
+//! ```
+//!
+//! A server function can have several guards, which all have to pass. Guards run after any
+//! middleware, and their source is listed by
+//! [`ServerFnTraitObj::guards`](crate::ServerFnTraitObj::guards). Tests can skip them by
+//! adding [`SkipGuards`] to the context of the call.
+
+use crate::error::ServerFnError;
+
+/// The reason that a guard rejected a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GuardRejection {
+    /// The client has not proven who it is. Sent as `401 Unauthorized`.
+    Unauthorized(String),
+    /// The client is not allowed to call the server function. Sent as `403 Forbidden`.
+    Forbidden(String),
+}
+
+impl GuardRejection {
+    /// Converts the rejection into the matching [`ServerFnError`].
+    pub fn into_error<CustErr>(self) -> ServerFnError<CustErr> {
+        match self {
+            GuardRejection::Unauthorized(reason) => ServerFnError::Unauthorized(reason),
+            GuardRejection::Forbidden(reason) => ServerFnError::Forbidden(reason),
+        }
+    }
+}
+
+/// When this is present in the [`ServerContext`](crate::context::ServerContext) of a call,
+/// the server function's guards are not run.
+///
+/// ```ignore
+/// let res = server_fns::axum::handle_server_fn_with_context(req, |cx| cx.insert(SkipGuards)).await;
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SkipGuards;
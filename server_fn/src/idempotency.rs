@@ -0,0 +1,115 @@
+//! Idempotency keys, so that a mutation that is sent more than once only runs once.
+//!
+//! [`IdempotentClient`] wraps another client and adds an [`IDEMPOTENCY_KEY_HEADER`] to each
+//! call. On the server, [`IdempotencyLayer`](crate::middleware::idempotency::IdempotencyLayer)
+//! runs the first `POST` with a given key as usual and stores its response, then sends the
+//! stored response to any later call with the same key instead of running the server function
+//! again.
+//!
+//! Each call gets a new random key unless one is set with [`with_idempotency_key`]. To make
+//! retries and double submissions safe, create one key for each logical action, like each
+//! time a form is shown, and use it for every attempt:
+//!
+//! ```ignore
+//! use server_fns::{
+//!     client::browser::BrowserClient,
+//!     idempotency::{new_key, with_idempotency_key, IdempotentClient},
+//! };
+//!
+//! #[server(client = IdempotentClient<BrowserClient>)]
+//! pub async fn place_order(cart: Cart) -> Result<OrderId, ServerFnError> {
+//!     todo!()
+//! }
+//!
+//! let key = new_key();
+//! // however often this runs, the order is only placed once
+//! with_idempotency_key(&key, place_order(cart)).await
+//! ```
+
+use crate::{
+    client::Client, codec::MessageStream, error::ServerFnError, random::random_u64,
+    request::ClientReq,
+};
+use serde::de::DeserializeOwned;
+use std::{
+    cell::RefCell,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+/// The header that carries the idempotency key of a call.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+thread_local! {
+    static CURRENT_KEY: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Creates a new random idempotency key.
+pub fn new_key() -> String {
+    format!("{:016x}{:016x}", random_u64(), random_u64())
+}
+
+/// Sends every call made with an [`IdempotentClient`] while `fut` runs with the given key.
+pub fn with_idempotency_key<F: Future>(key: &str, fut: F) -> WithIdempotencyKey<F> {
+    WithIdempotencyKey {
+        key: key.to_string(),
+        inner: Box::pin(fut),
+    }
+}
+
+/// A future that runs with an idempotency key, returned by [`with_idempotency_key`].
+pub struct WithIdempotencyKey<F> {
+    key: String,
+    inner: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for WithIdempotencyKey<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        struct Restore(Option<String>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                CURRENT_KEY.with(|current| *current.borrow_mut() = self.0.take());
+            }
+        }
+
+        let this = &mut *self;
+        let _restore = Restore(CURRENT_KEY.with(|current| current.replace(Some(this.key.clone()))));
+        this.inner.as_mut().poll(cx)
+    }
+}
+
+/// A [`Client`] that adds an idempotency key to each call, and sends it with the client `C`.
+///
+/// See the [module documentation](self) for details.
+pub struct IdempotentClient<C>(PhantomData<C>);
+
+impl<CustErr, C> Client<CustErr> for IdempotentClient<C>
+where
+    C: Client<CustErr>,
+    CustErr: Send,
+{
+    type Request = C::Request;
+    type Response = C::Response;
+
+    async fn send(req: Self::Request) -> Result<Self::Response, ServerFnError<CustErr>> {
+        let key = CURRENT_KEY
+            .with(|current| current.borrow().clone())
+            .unwrap_or_else(new_key);
+        C::send(req.try_add_header(IDEMPOTENCY_KEY_HEADER, &key)?).await
+    }
+
+    fn open_websocket(
+        path: &str,
+        input: MessageStream,
+    ) -> impl Future<Output = Result<MessageStream, ServerFnError<CustErr>>> + Send
+    where
+        CustErr: DeserializeOwned,
+    {
+        C::open_websocket(path, input)
+    }
+}
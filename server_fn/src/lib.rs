@@ -1,22 +1,48 @@
+// only the axum integration can serve batches, so a server that only uses actix can't
+// send them
+#[cfg(any(feature = "axum", not(feature = "actix")))]
+pub mod batch;
 pub mod client;
 pub mod codec;
+pub mod context;
+pub mod cors;
 #[macro_use]
 pub mod error;
+pub mod guard;
+pub mod idempotency;
 pub mod middleware;
+#[cfg(feature = "axum")]
+pub mod mock;
+mod random;
 pub mod redirect;
+pub mod replay;
 pub mod request;
 pub mod response;
+#[cfg(feature = "axum")]
+pub mod testing;
+#[cfg(feature = "tracing")]
+pub mod trace;
+pub mod validate;
 
 use client::Client;
 use codec::{Encoding, FromReq, FromRes, IntoReq, IntoRes};
+use context::{use_context, ServerContext};
+use cors::Cors;
 use dashmap::DashMap;
 pub use error::ServerFnError;
-use middleware::{Layer, Service};
+use guard::SkipGuards;
+use middleware::{BoxedService, Layer, Service};
 use once_cell::sync::Lazy;
+#[cfg(feature = "tracing")]
+use request::ClientReq;
 use request::Req;
-use response::{ClientRes, Res};
+use response::{ClientRes, Res, ResponseOptions};
 use serde::{de::DeserializeOwned, Serialize};
-use std::{future::Future, pin::Pin, sync::Arc};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, RwLock},
+};
 
 // reexports for the sake of the macro
 #[doc(hidden)]
@@ -40,6 +66,16 @@ where
 {
     const PATH: &'static str;
 
+    /// The source of each guard on this server function, for listing them.
+    const GUARDS: &'static [&'static str] = &[];
+
+    /// The largest request body that this server function accepts, in bytes, replacing the
+    /// global limit set with `set_body_limit` in the server integration.
+    ///
+    /// This is set with `#[server(body_limit = ...)]`. Larger requests are rejected with
+    /// `413 Payload Too Large` and [`ServerFnError::PayloadTooLarge`].
+    const BODY_LIMIT: Option<u64> = None;
+
     /// The type of the HTTP client that will send the request from the client side.
     ///
     /// For example, this might be `gloo-net` in the browser, or `reqwest` for a desktop app.
@@ -74,6 +110,48 @@ where
         Vec::new()
     }
 
+    /// Runs this server function's guards against the incoming request, before its
+    /// arguments are decoded.
+    ///
+    /// This is set with `#[server(guard = ...)]`. See the [`guard`] module.
+    fn guard(
+        req: &Self::ServerRequest,
+    ) -> impl Future<Output = Result<(), ServerFnError<Self::Error>>> + Send + 'static {
+        let _ = req;
+        async { Ok(()) }
+    }
+
+    /// Validates the arguments on the server, after they have been decoded and before
+    /// [`check`](Self::check) and the body of the server function run.
+    ///
+    /// The `#[server]` macro implements this using the arguments struct's
+    /// [`Validate`](validate::Validate) implementation, if it has one.
+    fn validate_args(&self) -> Result<(), ServerFnError<Self::Error>> {
+        Ok(())
+    }
+
+    /// The arguments formatted for logs, with any that are marked `#[server(sensitive)]`
+    /// redacted.
+    ///
+    /// The `#[server]` macro implements this using the arguments struct's `Debug`
+    /// implementation. With the `tracing` feature, it is logged when the arguments have been
+    /// decoded.
+    fn debug_args(&self) -> Option<String> {
+        None
+    }
+
+    /// Runs on the server after the arguments have been decoded, and before the body of the
+    /// server function.
+    ///
+    /// Returning an error skips the body and sends the error to the client instead. This can
+    /// be set with `#[server(check = path::to::function)]`, where the function is an `async fn`
+    /// that takes a reference to the arguments struct, so that rules which depend on the
+    /// arguments (like whether the user may edit a particular item) don't need to be
+    /// repeated in the body of each server function.
+    fn check(&self) -> impl Future<Output = Result<(), ServerFnError<Self::Error>>> + Send {
+        async { Ok(()) }
+    }
+
     // The body of the server function. This will only run on the server.
     fn run_body(
         self,
@@ -82,50 +160,100 @@ where
     fn run_on_server(
         req: Self::ServerRequest,
     ) -> impl Future<Output = Self::ServerResponse> + Send {
-        async {
-            Self::execute_on_server(req)
-                .await
-                .unwrap_or_else(Self::ServerResponse::error_response)
-        }
+        // each call gets its own context, which can still see anything provided by the handler
+        let context = ServerContext::current()
+            .map(|context| context.child())
+            .unwrap_or_default();
+        req.provide_context(&context);
+        let options = ResponseOptions::default();
+        context.insert(options.clone());
+        // requests from the server fn client accept the output encoding, not HTML, so
+        // this is a form that was submitted by the browser before (or without) hydration
+        let accepts_html = req
+            .accepts()
+            .map(|accepts| accepts.contains("text/html"))
+            .unwrap_or(false);
+        let referer = accepts_html.then(|| req.referer()).flatten();
+        #[cfg(feature = "tracing")]
+        let span = trace::server_span::<Self>(&req, &context);
+        #[cfg(feature = "tracing")]
+        let start = trace::now_ms();
+        let fut = context.scope(async move {
+            let res = Self::execute_on_server(req).await;
+            if let Some(referer) = referer {
+                let error = res
+                    .as_ref()
+                    .err()
+                    .and_then(|err| serde_json::to_string(err).ok());
+                redirect::redirect_to_referer(&options, &referer, Self::PATH, error);
+            }
+            redirect::set_redirect_status(&options, accepts_html);
+            #[cfg(feature = "tracing")]
+            {
+                let error = res.as_ref().err();
+                let status = options
+                    .status()
+                    .unwrap_or_else(|| error.map(ServerFnError::status_code).unwrap_or(200));
+                trace::record_outcome(status, error, start);
+            }
+            let res = res.unwrap_or_else(middleware::metrics::error_response);
+            res.with_options(&options)
+                .unwrap_or_else(middleware::metrics::error_response)
+        });
+        #[cfg(feature = "tracing")]
+        let fut = tracing::Instrument::instrument(fut, span);
+        fut
     }
 
     fn run_on_client(
         self,
     ) -> impl Future<Output = Result<Self::Output, ServerFnError<Self::Error>>> + Send {
-        async move {
+        #[cfg(feature = "tracing")]
+        let (span, trace) = trace::client_span::<Self>();
+        let fut = async move {
+            #[cfg(feature = "tracing")]
+            let start = trace::now_ms();
             // create and send request on client
             let req = self.into_req(Self::PATH, Self::OutputEncoding::CONTENT_TYPE)?;
-            let res = Self::Client::send(req).await?;
+            #[cfg(feature = "tracing")]
+            let req = req.try_add_header(trace::TRACEPARENT_HEADER, &trace.to_string())?;
+            let res = match Self::Client::send(req).await {
+                Ok(res) => res,
+                Err(error) => {
+                    // the request never reached the server, so there is no status
+                    #[cfg(feature = "tracing")]
+                    trace::record_outcome(0, Some(&error), start);
+                    return Err(error);
+                }
+            };
 
             let status = res.status();
             let location = res.location();
+            let has_redirect = res.has_redirect();
 
             // if it returns an error status, deserialize the error
             // this is the same logic as the current implementation of server fns
             // TODO I don't love that this requires shipping `serde_json` for errors
             let res = if (400..=599).contains(&status) {
-                let status_text = res.status_text();
-                let text = res.try_into_string().await?;
-                match serde_json::from_str(&text) {
-                    Ok(e) => Err(e),
-                    Err(_) => Err(ServerFnError::ServerError(if text.is_empty() {
-                        format!("{} {}", status, status_text)
-                    } else {
-                        format!("{} {}: {}", status, status_text, text)
-                    })),
-                }
+                Err(read_error(res).await)
             } else {
                 // otherwise, deserialize the body as is
                 Self::Output::from_res(res).await
             };
 
             // if redirected, call the redirect hook (if that's been set)
-            if (300..=399).contains(&status) {
+            if (300..=399).contains(&status) || has_redirect {
                 redirect::call_redirect_hook(&location);
             }
 
+            #[cfg(feature = "tracing")]
+            trace::record_outcome(status, res.as_ref().err(), start);
+
             res
-        }
+        };
+        #[cfg(feature = "tracing")]
+        let fut = tracing::Instrument::instrument(fut, span);
+        fut
     }
 
     #[doc(hidden)]
@@ -133,7 +261,16 @@ where
         req: Self::ServerRequest,
     ) -> impl Future<Output = Result<Self::ServerResponse, ServerFnError<Self::Error>>> + Send {
         async {
+            if use_context::<SkipGuards>().is_none() {
+                Self::guard(&req).await?;
+            }
             let this = Self::from_req(req).await?;
+            #[cfg(feature = "tracing")]
+            if let Some(args) = this.debug_args() {
+                tracing::debug!(args, "decoded arguments");
+            }
+            this.validate_args()?;
+            this.check().await?;
             let output = this.run_body().await?;
             let res = output.into_res().await?;
             Ok(res)
@@ -145,6 +282,28 @@ where
     }
 }
 
+/// Reads the error from a response with an error status.
+pub(crate) async fn read_error<CustErr, R>(res: R) -> ServerFnError<CustErr>
+where
+    CustErr: DeserializeOwned,
+    R: ClientRes<CustErr>,
+{
+    let status = res.status();
+    let status_text = res.status_text();
+    let text = match res.try_into_string().await {
+        Ok(text) => text,
+        Err(err) => return err,
+    };
+    match serde_json::from_str(&text) {
+        Ok(e) => e,
+        Err(_) => ServerFnError::ServerError(if text.is_empty() {
+            format!("{} {}", status, status_text)
+        } else {
+            format!("{} {}: {}", status, status_text, text)
+        }),
+    }
+}
+
 #[doc(hidden)]
 pub use inventory;
 
@@ -162,6 +321,10 @@ macro_rules! initialize_server_fn_map {
 
 pub struct ServerFnTraitObj<Req, Res> {
     path: &'static str,
+    method: &'static str,
+    content_type: &'static str,
+    guards: &'static [&'static str],
+    body_limit: Option<u64>,
     handler: fn(Req) -> Pin<Box<dyn Future<Output = Res> + Send>>,
     middleware: fn() -> Vec<Arc<dyn Layer<Req, Res>>>,
 }
@@ -169,11 +332,19 @@ pub struct ServerFnTraitObj<Req, Res> {
 impl<Req, Res> ServerFnTraitObj<Req, Res> {
     pub const fn new(
         path: &'static str,
+        method: &'static str,
+        content_type: &'static str,
+        guards: &'static [&'static str],
+        body_limit: Option<u64>,
         handler: fn(Req) -> Pin<Box<dyn Future<Output = Res> + Send>>,
         middleware: fn() -> Vec<Arc<dyn Layer<Req, Res>>>,
     ) -> Self {
         Self {
             path,
+            method,
+            content_type,
+            guards,
+            body_limit,
             handler,
             middleware,
         }
@@ -182,6 +353,47 @@ impl<Req, Res> ServerFnTraitObj<Req, Res> {
     pub fn path(&self) -> &'static str {
         self.path
     }
+
+    /// The HTTP method used to call the server function.
+    pub fn method(&self) -> &'static str {
+        self.method
+    }
+
+    /// The content type of the arguments sent to the server function.
+    pub fn content_type(&self) -> &'static str {
+        self.content_type
+    }
+
+    /// The source of each guard on the server function.
+    pub fn guards(&self) -> &'static [&'static str] {
+        self.guards
+    }
+
+    /// The largest request body that the server function accepts, if it has its own limit.
+    pub fn body_limit(&self) -> Option<u64> {
+        self.body_limit
+    }
+}
+
+impl<Req, Res> ServerFnTraitObj<Req, Res>
+where
+    Req: Send + 'static,
+    Res: 'static,
+{
+    /// Wraps the server function in its own middleware, and then in any global middleware
+    /// that applies to its path.
+    fn layered(&self, global: &[GlobalMiddleware<Req, Res>]) -> BoxedService<Req, Res> {
+        let mut service = BoxedService::new(*self);
+        for middleware in (self.middleware)() {
+            service = middleware.layer(service);
+        }
+        for (prefix, middleware) in global.iter().rev() {
+            if self.path.starts_with(prefix.as_str()) {
+                service = middleware.layer(service);
+            }
+        }
+        service
+    }
 }
 
 impl<Req, Res> Service<Req, Res> for ServerFnTraitObj<Req, Res>
@@ -189,7 +401,7 @@ where
     Req: Send + 'static,
     Res: 'static,
 {
-    fn run(&mut self, req: Req) -> Pin<Box<dyn Future<Output = Res> + Send>> {
+    fn run(&self, req: Req) -> Pin<Box<dyn Future<Output = Res> + Send>> {
         let handler = self.handler;
         Box::pin(async move { handler(req).await })
     }
@@ -205,45 +417,239 @@ impl<Req, Res> Copy for ServerFnTraitObj<Req, Res> {}
 
 type LazyServerFnMap<Req, Res> = Lazy<DashMap<&'static str, ServerFnTraitObj<Req, Res>>>;
 
+type GlobalMiddleware<Req, Res> = (String, Arc<dyn Layer<Req, Res>>);
+
+type MiddlewareList<Req, Res> = RwLock<Vec<GlobalMiddleware<Req, Res>>>;
+
+type LazyLayeredServerFnMap<Req, Res> = Lazy<DashMap<&'static str, BoxedService<Req, Res>>>;
+
+type CorsConfig = RwLock<Option<Arc<Cors>>>;
+
+type BodyLimitConfig = RwLock<Option<u64>>;
+
+/// The server functions registered for one server integration, along with its global
+/// middleware, the middleware stacks that have been built from them, its CORS
+/// configuration and its global body limit.
+struct ServerFnRegistry<Req: 'static, Res: 'static> {
+    server_fns: &'static LazyServerFnMap<Req, Res>,
+    middleware: &'static MiddlewareList<Req, Res>,
+    layered: &'static LazyLayeredServerFnMap<Req, Res>,
+    cors: &'static CorsConfig,
+    body_limit: &'static BodyLimitConfig,
+}
+
+impl<Req, Res> ServerFnRegistry<Req, Res>
+where
+    Req: Send + 'static,
+    Res: 'static,
+{
+    fn register(&self, server_fn: ServerFnTraitObj<Req, Res>) {
+        // holding the middleware lock means that a stale stack can't be cached meanwhile
+        let _middleware = self.middleware.write().expect("global middleware lock poisoned");
+        self.server_fns.insert(server_fn.path, server_fn);
+        self.layered.remove(server_fn.path);
+    }
+
+    fn set_cors(&self, cors: Cors) {
+        cors.assert_valid();
+        *self.cors.write().expect("CORS lock poisoned") = Some(Arc::new(cors));
+    }
+
+    fn cors(&self) -> Option<Arc<Cors>> {
+        self.cors.read().expect("CORS lock poisoned").clone()
+    }
+
+    fn set_body_limit(&self, limit: Option<u64>) {
+        *self.body_limit.write().expect("body limit lock poisoned") = limit;
+    }
+
+    /// The body limit for the server function at `path`: its own, or else the global one.
+    fn body_limit(&self, path: &str) -> Option<u64> {
+        self.server_fns
+            .get(path)
+            .and_then(|server_fn| server_fn.body_limit)
+            .or_else(|| *self.body_limit.read().expect("body limit lock poisoned"))
+    }
+
+    /// Every registered server function.
+    fn all(&self) -> Vec<ServerFnTraitObj<Req, Res>> {
+        self.server_fns.iter().map(|server_fn| *server_fn).collect()
+    }
+
+    /// The HTTP method of the server function at `path`, if there is one.
+    fn method(&self, path: &str) -> Option<&'static str> {
+        self.server_fns.get(path).map(|server_fn| server_fn.method)
+    }
+
+    fn register_middleware(&self, prefix: String, middleware: Arc<dyn Layer<Req, Res>>) {
+        let mut global = self.middleware.write().expect("global middleware lock poisoned");
+        global.push((prefix, middleware));
+        self.layered.clear();
+    }
+
+    /// Returns the server function at `path`, wrapped in all of its middleware.
+    ///
+    /// The middleware stack is only built the first time a server function is called, and
+    /// then shared by later calls.
+    fn get(&self, path: &str) -> Option<BoxedService<Req, Res>> {
+        if let Some(service) = self.layered.get(path) {
+            return Some(service.clone());
+        }
+        let global = self.middleware.read().expect("global middleware lock poisoned");
+        let server_fn = *self.server_fns.get(path)?;
+        let service = self
+            .layered
+            .entry(server_fn.path)
+            .or_insert_with(|| server_fn.layered(&global));
+        Some(service.clone())
+    }
+}
+
 // Axum integration
 #[cfg(feature = "axum")]
 pub mod axum {
     use crate::{
-        middleware::{BoxedService, Layer, Service},
-        LazyServerFnMap, ServerFn, ServerFnTraitObj,
+        batch::{BatchEntry, BatchEntryResult, ENTRY_HEADERS, MAX_BATCH_SIZE},
+        codec::Encoding,
+        context::{use_context, ServerContext},
+        cors::Cors,
+        error::NoCustomError,
+        middleware::{
+            metrics::{self, Metrics},
+            Layer,
+        },
+        request::{limit_body, Req},
+        response::Res,
+        BodyLimitConfig, CorsConfig, LazyLayeredServerFnMap, LazyServerFnMap, MiddlewareList, ServerFn,
+        ServerFnError, ServerFnRegistry, ServerFnTraitObj,
+    };
+    use axum::{body::Body, extract::FromRequestParts};
+    use dashmap::DashMap;
+    use http_body_util::BodyExt;
+    use once_cell::sync::Lazy;
+    use http::{
+        header::{
+            HeaderName, HeaderValue, ACCEPT, CONTENT_LENGTH, CONTENT_TYPE, ORIGIN, SET_COOKIE,
+            VARY,
+        },
+        request::Parts,
+        Method, Request, Response, StatusCode,
     };
-    use axum::body::Body;
-    use http::{Request, Response, StatusCode};
+    use std::{fmt::Debug, sync::Arc};
 
     inventory::collect!(ServerFnTraitObj<Request<Body>, Response<Body>>);
 
     static REGISTERED_SERVER_FUNCTIONS: LazyServerFnMap<Request<Body>, Response<Body>> =
         initialize_server_fn_map!(Request<Body>, Response<Body>);
 
+    static GLOBAL_MIDDLEWARE: MiddlewareList<Request<Body>, Response<Body>> =
+        MiddlewareList::new(Vec::new());
+
+    static LAYERED_SERVER_FUNCTIONS: LazyLayeredServerFnMap<Request<Body>, Response<Body>> =
+        Lazy::new(DashMap::new);
+
+    static CORS: CorsConfig = CorsConfig::new(None);
+
+    static BODY_LIMIT: BodyLimitConfig = BodyLimitConfig::new(None);
+
+    static REGISTRY: ServerFnRegistry<Request<Body>, Response<Body>> = ServerFnRegistry {
+        server_fns: &REGISTERED_SERVER_FUNCTIONS,
+        middleware: &GLOBAL_MIDDLEWARE,
+        layered: &LAYERED_SERVER_FUNCTIONS,
+        cors: &CORS,
+        body_limit: &BODY_LIMIT,
+    };
+
     pub fn register_explicit<T>()
     where
         T: ServerFn<ServerRequest = Request<Body>, ServerResponse = Response<Body>> + 'static,
     {
-        REGISTERED_SERVER_FUNCTIONS.insert(
+        REGISTRY.register(ServerFnTraitObj::new(
             T::PATH,
-            ServerFnTraitObj::new(
-                T::PATH,
-                |req| Box::pin(T::run_on_server(req)),
-                T::middlewares,
-            ),
-        );
+            T::InputEncoding::METHOD,
+            T::InputEncoding::CONTENT_TYPE,
+            T::GUARDS,
+            T::BODY_LIMIT,
+            |req| Box::pin(T::run_on_server(req)),
+            T::middlewares,
+        ));
+    }
+
+    /// Returns every registered server function, for example to list their paths and guards.
+    pub fn server_fns() -> Vec<ServerFnTraitObj<Request<Body>, Response<Body>>> {
+        REGISTRY.all()
+    }
+
+    /// Sets the largest request body that any server function accepts, in bytes, unless it
+    /// has its own `#[server(body_limit = ...)]`. Larger requests are rejected with
+    /// `413 Payload Too Large`, whether the body is read all at once or streamed.
+    ///
+    /// There is no global limit by default.
+    pub fn set_body_limit(limit: u64) {
+        REGISTRY.set_body_limit(Some(limit));
+    }
+
+    /// Sets the [`Cors`] configuration for all server functions, replacing any previous one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the configuration allows credentials from any origin.
+    pub fn set_cors(cors: Cors) {
+        REGISTRY.set_cors(cors);
+    }
+
+    pub(crate) fn add_headers(res: &mut Response<Body>, headers: Vec<(&'static str, String)>) {
+        for (name, value) in headers {
+            let name = HeaderName::from_static(name);
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                if name == VARY {
+                    res.headers_mut().append(name, value);
+                } else {
+                    res.headers_mut().insert(name, value);
+                }
+            }
+        }
+    }
+
+    /// Adds middleware that will be applied to every server function.
+    ///
+    /// Global middleware wraps any middleware added to an individual server function with
+    /// `#[middleware]`, so it runs first. Global middleware that is registered earlier wraps
+    /// (and so runs before) global middleware that is registered later.
+    pub fn register_middleware(middleware: impl Layer<Request<Body>, Response<Body>>) {
+        register_middleware_for_prefix("", middleware);
+    }
+
+    /// Adds middleware that will be applied to every server function whose path starts with
+    /// `prefix`, in the same order as [`register_middleware`].
+    pub fn register_middleware_for_prefix(
+        prefix: impl Into<String>,
+        middleware: impl Layer<Request<Body>, Response<Body>>,
+    ) {
+        REGISTRY.register_middleware(prefix.into(), Arc::new(middleware));
     }
 
     pub async fn handle_server_fn(req: Request<Body>) -> Response<Body> {
-        let path = req.uri().path();
+        let path = req.uri().path().to_string();
+        let cors = REGISTRY.cors();
+        let origin = req
+            .headers()
+            .get(ORIGIN)
+            .and_then(|origin| origin.to_str().ok())
+            .map(String::from);
 
-        if let Some(server_fn) = REGISTERED_SERVER_FUNCTIONS.get(path) {
-            let middleware = (server_fn.middleware)();
-            let mut service = BoxedService::new(*server_fn);
-            for middleware in middleware {
-                service = middleware.layer(service);
+        // answer CORS preflight requests with the method of the server function
+        if let (Some(cors), &Method::OPTIONS) = (&cors, req.method()) {
+            if let Some(method) = REGISTRY.method(&path) {
+                return preflight_response(cors, origin.as_deref(), method);
             }
-            service.run(req).await
+        }
+
+        if let Some(mut res) = dispatch(req).await {
+            if let Some(cors) = cors {
+                add_headers(&mut res, cors.response_headers(origin.as_deref()));
+            }
+            res
         } else {
             Response::builder()
                 .status(StatusCode::BAD_REQUEST)
@@ -253,45 +659,447 @@ pub mod axum {
                 .unwrap()
         }
     }
+
+    fn preflight_response(cors: &Cors, origin: Option<&str>, method: &str) -> Response<Body> {
+        let mut res = Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(Body::empty())
+            .unwrap();
+        add_headers(&mut res, cors.preflight_headers(origin, method));
+        res
+    }
+
+    /// Runs the server function at the request's path, with its middleware and body limit,
+    /// or returns `None` if there is no server function at that path.
+    pub(crate) async fn dispatch(req: Request<Body>) -> Option<Response<Body>> {
+        let path = req.uri().path();
+        let service = REGISTRY.get(path)?;
+        let limited = match REGISTRY.body_limit(path) {
+            Some(limit) => limit_body(req, limit),
+            None => Ok(req),
+        };
+        Some(match limited {
+            Ok(req) => service.0.run(req).await,
+            Err(err) => Res::<NoCustomError>::error_response(err),
+        })
+    }
+
+    /// Handles a batch of server function calls sent by
+    /// [`BatchClient`](crate::batch::BatchClient), which should be mounted at the path that
+    /// it sends batches to (see [`set_batch_path`](crate::batch::set_batch_path)).
+    ///
+    /// The calls run concurrently, each with its own middleware and body limit, as if they
+    /// had been sent on their own. They share the headers (like cookies) and extensions of
+    /// the batch request, and any cookies that they set are added to the batch response.
+    /// The global body limit, if any, applies to the whole batch.
+    ///
+    /// Batches must be sent as `application/json`, which a cross-site HTML form can't do, and
+    /// can have at most [`MAX_BATCH_SIZE`] calls.
+    pub async fn handle_batch(req: Request<Body>) -> Response<Body> {
+        let cors = REGISTRY.cors();
+        let origin = req
+            .headers()
+            .get(ORIGIN)
+            .and_then(|origin| origin.to_str().ok())
+            .map(String::from);
+
+        if let (Some(cors), &Method::OPTIONS) = (&cors, req.method()) {
+            return preflight_response(cors, origin.as_deref(), "POST");
+        }
+
+        let is_json = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .and_then(|content_type| content_type.split(';').next())
+            .is_some_and(|content_type| {
+                content_type.trim().eq_ignore_ascii_case("application/json")
+            });
+        let mut res = if !is_json {
+            Response::builder()
+                .status(StatusCode::UNSUPPORTED_MEDIA_TYPE)
+                .body(Body::from("batches must be sent as application/json"))
+                .unwrap()
+        } else {
+            match run_batch(req).await {
+                Ok(res) => res,
+                Err(err) => Res::<NoCustomError>::error_response(err),
+            }
+        };
+        if let Some(cors) = cors {
+            add_headers(&mut res, cors.response_headers(origin.as_deref()));
+        }
+        res
+    }
+
+    async fn run_batch(req: Request<Body>) -> Result<Response<Body>, ServerFnError> {
+        let (parts, body) = req.into_parts();
+        let req = Request::from_parts(parts.clone(), body);
+        // no server function has an empty path, so this is the global limit
+        let req = match REGISTRY.body_limit("") {
+            Some(limit) => limit_body(req, limit)?,
+            None => req,
+        };
+        let body = Req::<NoCustomError>::try_into_string(req).await?;
+        let entries = serde_json::from_str::<Vec<BatchEntry>>(&body)
+            .map_err(|e| ServerFnError::Deserialization(e.to_string()))?;
+        if entries.len() > MAX_BATCH_SIZE {
+            return Err(ServerFnError::Request(format!(
+                "a batch can have at most {MAX_BATCH_SIZE} calls, but this one has {}",
+                entries.len()
+            )));
+        }
+
+        let results = futures::future::join_all(entries.into_iter().map(|entry| {
+            let parts = &parts;
+            async move {
+                let path = entry.path.clone();
+                let res = match entry_request(parts, entry) {
+                    Ok(req) => dispatch(req).await.unwrap_or_else(|| {
+                        Res::<NoCustomError>::error_response(ServerFnError::Registration(
+                            format!("no server function at {path}"),
+                        ))
+                    }),
+                    Err(err) => Res::<NoCustomError>::error_response(err),
+                };
+                entry_result(res).await
+            }
+        }))
+        .await;
+
+        let mut cookies = Vec::new();
+        let results = results
+            .into_iter()
+            .map(|(result, set_cookies)| {
+                cookies.extend(set_cookies);
+                result
+            })
+            .collect::<Vec<_>>();
+        let body = serde_json::to_string(&results)
+            .map_err(|e| ServerFnError::Serialization(e.to_string()))?;
+        let mut res = Response::builder()
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .unwrap();
+        for cookie in cookies {
+            res.headers_mut().append(SET_COOKIE, cookie);
+        }
+        Ok(res)
+    }
+
+    /// Builds the request for one call in a batch, with the headers and extensions of the
+    /// batch request. The call can only set the [`ENTRY_HEADERS`] that the batch request
+    /// doesn't already have.
+    fn entry_request(parts: &Parts, entry: BatchEntry) -> Result<Request<Body>, ServerFnError> {
+        let body = entry
+            .body()
+            .map_err(|e| ServerFnError::Deserialization(e.to_string()))?;
+        let uri = match &entry.query {
+            Some(query) => format!("{}?{query}", entry.path),
+            None => entry.path,
+        };
+        let mut req = Request::builder()
+            .method(entry.method.as_str())
+            .uri(uri)
+            .version(parts.version)
+            .header(CONTENT_LENGTH, body.len())
+            .body(Body::from(body))
+            .map_err(|e| ServerFnError::Request(e.to_string()))?;
+        *req.extensions_mut() = parts.extensions.clone();
+        let headers = req.headers_mut();
+        for (name, value) in &parts.headers {
+            if name != CONTENT_TYPE && name != CONTENT_LENGTH && name != ACCEPT {
+                headers.append(name, value.clone());
+            }
+        }
+        for (name, value) in entry.headers {
+            let name = HeaderName::try_from(name)
+                .map_err(|e| ServerFnError::Request(e.to_string()))?;
+            if !ENTRY_HEADERS.contains(&name.as_str()) {
+                return Err(ServerFnError::Request(format!(
+                    "a call in a batch can't set the {name} header"
+                )));
+            }
+            if headers.contains_key(&name) {
+                continue;
+            }
+            let value = HeaderValue::try_from(value)
+                .map_err(|e| ServerFnError::Request(e.to_string()))?;
+            headers.insert(name, value);
+        }
+        Ok(req)
+    }
+
+    /// Reads the response to one call in a batch, along with any cookies that it sets.
+    async fn entry_result(res: Response<Body>) -> (BatchEntryResult, Vec<HeaderValue>) {
+        let (parts, body) = res.into_parts();
+        let body = match body.collect().await {
+            Ok(body) => body.to_bytes(),
+            Err(e) => {
+                let res = Res::<NoCustomError>::error_response(ServerFnError::Response(e.to_string()));
+                return Box::pin(entry_result(res)).await;
+            }
+        };
+        let mut cookies = Vec::new();
+        let mut headers = Vec::new();
+        for (name, value) in parts.headers {
+            let Some(name) = name else { continue };
+            if name == SET_COOKIE {
+                cookies.push(value);
+            } else if let Ok(value) = value.to_str() {
+                headers.push((name.to_string(), value.to_string()));
+            }
+        }
+        (
+            BatchEntryResult::new(parts.status.as_u16(), headers, &body),
+            cookies,
+        )
+    }
+
+    /// Responds with the [`Metrics::global`] recorded by
+    /// [`MetricsLayer`](crate::middleware::metrics::MetricsLayer), in the Prometheus text
+    /// format.
+    pub async fn handle_metrics() -> Response<Body> {
+        Response::builder()
+            .header(CONTENT_TYPE, metrics::CONTENT_TYPE)
+            .body(Body::from(Metrics::global().render()))
+            .unwrap()
+    }
+
+    /// Handles the request like [`handle_server_fn`], after adding any additional values
+    /// to the [`ServerContext`] in which the server function runs.
+    ///
+    /// This can be used to make things like your application state available to the body
+    /// of a server function with [`use_context`].
+    pub async fn handle_server_fn_with_context(
+        req: Request<Body>,
+        additional_context: impl FnOnce(&ServerContext),
+    ) -> Response<Body> {
+        let context = ServerContext::new();
+        additional_context(&context);
+        context.scope(handle_server_fn(req)).await
+    }
+
+    /// Runs an `axum` extractor against the request that is being handled by the current
+    /// server function.
+    ///
+    /// This can only be called from the body of a server function, while it is running on the server.
+    pub async fn extract<T>() -> Result<T, ServerFnError>
+    where
+        T: FromRequestParts<()>,
+        T::Rejection: Debug,
+    {
+        extract_with_state(&()).await
+    }
+
+    /// Runs an `axum` extractor that requires some application state against the request
+    /// that is being handled by the current server function.
+    ///
+    /// This can only be called from the body of a server function, while it is running on the server.
+    pub async fn extract_with_state<T, S>(state: &S) -> Result<T, ServerFnError>
+    where
+        T: FromRequestParts<S>,
+        T::Rejection: Debug,
+    {
+        let mut parts = use_context::<Parts>().ok_or_else(|| {
+            ServerFnError::ServerError(
+                "extract() should only be called from within a server function".into(),
+            )
+        })?;
+        T::from_request_parts(&mut parts, state)
+            .await
+            .map_err(|e| ServerFnError::ServerError(format!("{e:?}")))
+    }
 }
 
 // Actix integration
 #[cfg(feature = "actix")]
 pub mod actix {
-    use actix_web::{HttpRequest, HttpResponse};
+    use actix_web::{
+        http::{
+            header::{HeaderName, HeaderValue, ORIGIN, VARY},
+            Method,
+        },
+        web, FromRequest, HttpRequest, HttpResponse,
+    };
     use send_wrapper::SendWrapper;
+    use std::{fmt::Display, sync::Arc};
 
-    use crate::request::actix::ActixRequest;
-    use crate::response::actix::ActixResponse;
-    use crate::{LazyServerFnMap, ServerFn, ServerFnTraitObj};
+    use crate::codec::Encoding;
+    use crate::context::use_context;
+    use crate::cors::Cors;
+    use crate::middleware::metrics::{self, Metrics};
+    use crate::middleware::Layer;
+    use crate::error::NoCustomError;
+    use crate::request::{actix::ActixRequest, limit_body};
+    use crate::response::{actix::ActixResponse, Res};
+    use crate::{
+        BodyLimitConfig, CorsConfig, LazyLayeredServerFnMap, LazyServerFnMap, MiddlewareList, ServerFn,
+        ServerFnError, ServerFnRegistry, ServerFnTraitObj,
+    };
+    use dashmap::DashMap;
+    use once_cell::sync::Lazy;
 
     inventory::collect!(ServerFnTraitObj<ActixRequest, ActixResponse>);
 
     static REGISTERED_SERVER_FUNCTIONS: LazyServerFnMap<ActixRequest, ActixResponse> =
         initialize_server_fn_map!(ActixRequest, ActixResponse);
 
+    static GLOBAL_MIDDLEWARE: MiddlewareList<ActixRequest, ActixResponse> =
+        MiddlewareList::new(Vec::new());
+
+    static LAYERED_SERVER_FUNCTIONS: LazyLayeredServerFnMap<ActixRequest, ActixResponse> =
+        Lazy::new(DashMap::new);
+
+    static CORS: CorsConfig = CorsConfig::new(None);
+
+    static BODY_LIMIT: BodyLimitConfig = BodyLimitConfig::new(None);
+
+    static REGISTRY: ServerFnRegistry<ActixRequest, ActixResponse> = ServerFnRegistry {
+        server_fns: &REGISTERED_SERVER_FUNCTIONS,
+        middleware: &GLOBAL_MIDDLEWARE,
+        layered: &LAYERED_SERVER_FUNCTIONS,
+        cors: &CORS,
+        body_limit: &BODY_LIMIT,
+    };
+
     pub fn register_explicit<T>()
     where
         T: ServerFn<ServerRequest = ActixRequest, ServerResponse = ActixResponse> + 'static,
     {
-        REGISTERED_SERVER_FUNCTIONS.insert(
+        REGISTRY.register(ServerFnTraitObj::new(
             T::PATH,
-            ServerFnTraitObj::new(T::PATH, |req| Box::pin(T::run_on_server(req))),
-        );
+            T::InputEncoding::METHOD,
+            T::InputEncoding::CONTENT_TYPE,
+            T::GUARDS,
+            T::BODY_LIMIT,
+            |req| Box::pin(T::run_on_server(req)),
+            T::middlewares,
+        ));
+    }
+
+    /// Returns every registered server function, for example to list their paths and guards.
+    pub fn server_fns() -> Vec<ServerFnTraitObj<ActixRequest, ActixResponse>> {
+        REGISTRY.all()
     }
 
-    pub async fn handle_server_fn(req: HttpRequest) -> HttpResponse {
+    /// Sets the largest request body that any server function accepts, in bytes, unless it
+    /// has its own `#[server(body_limit = ...)]`. Larger requests are rejected with
+    /// `413 Payload Too Large`, whether the body is read all at once or streamed.
+    ///
+    /// There is no global limit by default.
+    pub fn set_body_limit(limit: u64) {
+        REGISTRY.set_body_limit(Some(limit));
+    }
+
+    /// Sets the [`Cors`] configuration for all server functions, replacing any previous one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the configuration allows credentials from any origin.
+    pub fn set_cors(cors: Cors) {
+        REGISTRY.set_cors(cors);
+    }
+
+    fn add_headers(res: &mut HttpResponse, headers: Vec<(&'static str, String)>) {
+        for (name, value) in headers {
+            let name = HeaderName::from_static(name);
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                if name == VARY {
+                    res.headers_mut().append(name, value);
+                } else {
+                    res.headers_mut().insert(name, value);
+                }
+            }
+        }
+    }
+
+    /// Adds middleware that will be applied to every server function.
+    ///
+    /// Global middleware wraps any middleware added to an individual server function with
+    /// `#[middleware]`, so it runs first. Global middleware that is registered earlier wraps
+    /// (and so runs before) global middleware that is registered later.
+    pub fn register_middleware(middleware: impl Layer<ActixRequest, ActixResponse>) {
+        register_middleware_for_prefix("", middleware);
+    }
+
+    /// Adds middleware that will be applied to every server function whose path starts with
+    /// `prefix`, in the same order as [`register_middleware`].
+    pub fn register_middleware_for_prefix(
+        prefix: impl Into<String>,
+        middleware: impl Layer<ActixRequest, ActixResponse>,
+    ) {
+        REGISTRY.register_middleware(prefix.into(), Arc::new(middleware));
+    }
+
+    pub async fn handle_server_fn(req: HttpRequest, payload: web::Payload) -> HttpResponse {
         let path = req.uri().path();
-        if let Some(server_fn) = REGISTERED_SERVER_FUNCTIONS.get(path) {
-            server_fn
-                .run(ActixRequest(SendWrapper::new(req)))
-                .await
-                .0
-                .take()
+        let cors = REGISTRY.cors();
+        let origin = req
+            .headers()
+            .get(ORIGIN)
+            .and_then(|origin| origin.to_str().ok())
+            .map(String::from);
+
+        // answer CORS preflight requests with the method of the server function
+        if let (Some(cors), &Method::OPTIONS) = (&cors, req.method()) {
+            if let Some(method) = REGISTRY.method(path) {
+                let mut res = HttpResponse::NoContent().finish();
+                add_headers(&mut res, cors.preflight_headers(origin.as_deref(), method));
+                return res;
+            }
+        }
+
+        if let Some(service) = REGISTRY.get(path) {
+            let limit = REGISTRY.body_limit(path);
+            let req = ActixRequest::from((req, payload.into_inner()));
+            let limited = match limit {
+                Some(limit) => limit_body(req, limit),
+                None => Ok(req),
+            };
+            let mut res = match limited {
+                Ok(req) => service.0.run(req).await,
+                Err(err) => Res::<NoCustomError>::error_response(err),
+            }
+            .into_inner();
+            if let Some(cors) = cors {
+                add_headers(&mut res, cors.response_headers(origin.as_deref()));
+            }
+            res
         } else {
             HttpResponse::BadRequest().body(format!(
                 "Could not find a server function at the route {path}. \n\nIt's likely that either\n 1. The API prefix you specify in the `#[server]` macro doesn't match the prefix at which your server function handler is mounted, or \n2. You are on a platform that doesn't support automatic server function registration and you need to call ServerFn::register_explicit() on the server function type, somewhere in your `main` function.",
             ))
         }
     }
+
+    /// Responds with the [`Metrics::global`] recorded by
+    /// [`MetricsLayer`](crate::middleware::metrics::MetricsLayer), in the Prometheus text
+    /// format.
+    pub async fn handle_metrics() -> HttpResponse {
+        HttpResponse::Ok()
+            .content_type(metrics::CONTENT_TYPE)
+            .body(Metrics::global().render())
+    }
+
+    /// Runs an `actix-web` extractor against the request that is being handled by the current
+    /// server function.
+    ///
+    /// This can only be called from the body of a server function, while it is running on the server.
+    pub async fn extract<T>() -> Result<T, ServerFnError>
+    where
+        T: FromRequest,
+        T::Error: Display,
+    {
+        let req = use_context::<ActixRequest>().ok_or_else(|| {
+            ServerFnError::ServerError(
+                "extract() should only be called from within a server function".into(),
+            )
+        })?;
+        // Actix is going to keep this on a single thread anyway so it's fine to wrap it
+        // with SendWrapper, which makes it `Send` but will panic if it moves to another thread
+        SendWrapper::new(T::extract(&req.0))
+            .await
+            .map_err(|e| ServerFnError::ServerError(e.to_string()))
+    }
 }
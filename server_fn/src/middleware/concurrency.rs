@@ -0,0 +1,94 @@
+//! Limits on how many calls to a server function can run at once.
+//!
+//! [`ConcurrencyLimitLayer`] lets a fixed number of calls run at the same time. Any further
+//! calls wait in a queue for one of them to finish, in the order that they arrived. A call
+//! that has waited for longer than the queue timeout (5 seconds by default) is rejected
+//! with `503 Service Unavailable` and a [`ServerFnError::Unavailable`], which clients can
+//! retry later.
+//!
+//! This is usually set with `#[server(max_concurrent = ...)]`, along with
+//! `queue_timeout = "..."` to change how long calls wait:
+//!
+//! ```ignore
+//! // expensive reports can't use up every worker
+//! #[server(max_concurrent = 4, queue_timeout = "2s", timeout = "30s")]
+//! pub async fn generate_report(id: u32) -> Result<Report, ServerFnError> {
+//!     todo!()
+//! }
+//! ```
+//!
+//! The limit is shared by every clone of the layer, so a layer that is registered as global
+//! middleware limits the total number of calls to all of the server functions it applies to.
+
+use super::{metrics, BoxedService, Layer, Service};
+use crate::error::{NoCustomError, ServerFnError};
+use std::{future::Future, marker::PhantomData, pin::Pin, sync::Arc, time::Duration};
+use tokio::sync::Semaphore;
+
+/// Middleware that limits how many calls can run at once.
+///
+/// See the [module documentation](self) for details.
+#[derive(Debug, Clone)]
+pub struct ConcurrencyLimitLayer {
+    max: usize,
+    queue_timeout: Duration,
+    semaphore: Arc<Semaphore>,
+}
+
+impl ConcurrencyLimitLayer {
+    /// Allows at most `max` calls to run at once.
+    pub fn new(max: usize) -> Self {
+        Self {
+            max,
+            queue_timeout: Duration::from_secs(5),
+            semaphore: Arc::new(Semaphore::new(max)),
+        }
+    }
+
+    /// Sets how long a call can wait for another to finish before it is rejected.
+    pub fn queue_timeout(mut self, queue_timeout: Duration) -> Self {
+        self.queue_timeout = queue_timeout;
+        self
+    }
+}
+
+impl<Req, Res> Layer<Req, Res> for ConcurrencyLimitLayer
+where
+    Req: crate::request::Req<NoCustomError> + Send + 'static,
+    Res: crate::response::Res<NoCustomError> + Send + 'static,
+{
+    fn layer(&self, inner: BoxedService<Req, Res>) -> BoxedService<Req, Res> {
+        BoxedService::new(ConcurrencyLimit {
+            layer: self.clone(),
+            inner,
+            ty: PhantomData,
+        })
+    }
+}
+
+struct ConcurrencyLimit<Req, Res> {
+    layer: ConcurrencyLimitLayer,
+    inner: BoxedService<Req, Res>,
+    ty: PhantomData<fn(Req) -> Res>,
+}
+
+impl<Req, Res> Service<Req, Res> for ConcurrencyLimit<Req, Res>
+where
+    Req: crate::request::Req<NoCustomError> + Send + 'static,
+    Res: crate::response::Res<NoCustomError> + Send + 'static,
+{
+    fn run(&self, req: Req) -> Pin<Box<dyn Future<Output = Res> + Send>> {
+        let layer = self.layer.clone();
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            let acquire = Arc::clone(&layer.semaphore).acquire_owned();
+            let Ok(Ok(_permit)) = tokio::time::timeout(layer.queue_timeout, acquire).await else {
+                return metrics::error_response(ServerFnError::Unavailable(format!(
+                    "{} calls are already running",
+                    layer.max
+                )));
+            };
+            inner.0.run(req).await
+        })
+    }
+}
@@ -0,0 +1,166 @@
+//! Protection against cross-site request forgery (CSRF).
+//!
+//! Server functions that accept form-encoded bodies (like [`PostUrl`](crate::codec::PostUrl))
+//! can be called by a plain HTML form on any other site, and the browser will send the
+//! user's cookies along with it. [`CsrfLayer`] rejects these requests by checking the
+//! headers that browsers add to every request to say where it came from:
+//!
+//! 1. CORS preflight requests are always allowed, as browsers never send cookies with them.
+//!    Every other request is checked, whatever its method, because server functions can be
+//!    called with `GET` (like [`GetUrl`](crate::codec::GetUrl)) and WebSocket connections
+//!    are opened with `GET`.
+//! 2. WebSocket upgrades are allowed if their `Origin` header matches the app's own origin.
+//!    Browsers send it with every handshake, and don't apply CORS to WebSockets, so it is
+//!    the only thing that stops another site from connecting as the user.
+//! 3. For other requests, if the `Sec-Fetch-Site` header is present, the request is allowed
+//!    if it is `same-origin` or `none` (typed into the address bar, for example).
+//! 4. Otherwise, if the `Origin` header is present, the request is allowed if it matches the
+//!    app's own origin: the `Host` header, with the scheme from `X-Forwarded-Proto` or else
+//!    `https`. On `localhost`, `http` is allowed as well. Apps served over plain HTTP on any
+//!    other host should trust their own origin with [`CsrfLayer::trust_origin`].
+//! 5. Requests with neither header were not made by a browser, so they cannot carry the
+//!    user's cookies without the caller's involvement, and are allowed. (Browsers that are
+//!    too old to send `Sec-Fetch-Site` don't send `Origin` with `GET` requests either, so
+//!    they are only protected for other methods.)
+//!
+//! Any origin that was explicitly trusted with [`CsrfLayer::trust_origin`] is also allowed.
+//! Because browsers add these headers themselves, the browser client needs no changes.
+//! Rejected requests receive `403 Forbidden` and a [`ServerFnError::Forbidden`].
+//!
+//! ```ignore
+//! use server_fns::middleware::csrf::CsrfLayer;
+//!
+//! // protect every server function
+//! server_fns::axum::register_middleware(CsrfLayer::new());
+//!
+//! // or only one
+//! #[server]
+//! #[middleware(CsrfLayer::new().trust_origin("https://admin.example.com"))]
+//! pub async fn delete_account() -> Result<(), ServerFnError> {
+//!     todo!()
+//! }
+//! ```
+
+use super::{metrics, BoxedService, Layer, Service};
+use crate::error::{NoCustomError, ServerFnError};
+use std::{future::Future, marker::PhantomData, pin::Pin, sync::Arc};
+
+/// Middleware that rejects cross-origin requests made by a browser.
+///
+/// See the [module documentation](self) for the rules it applies.
+#[derive(Debug, Clone, Default)]
+pub struct CsrfLayer {
+    trusted: Arc<Vec<String>>,
+}
+
+impl CsrfLayer {
+    /// Only allows same-origin requests from browsers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also allows requests from the given origin, like `https://app.example.com`.
+    pub fn trust_origin(mut self, origin: impl Into<String>) -> Self {
+        let origin = origin.into();
+        Arc::make_mut(&mut self.trusted).push(origin.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Checks the request, returning why it was rejected if it is not allowed.
+    pub fn verify<R: crate::request::Req<NoCustomError>>(&self, req: &R) -> Result<(), String> {
+        if req.method() == "OPTIONS" && req.header("access-control-request-method").is_some() {
+            return Ok(());
+        }
+
+        let origin = req.header("origin");
+        if origin
+            .as_deref()
+            .is_some_and(|origin| self.trusted.iter().any(|trusted| trusted == origin))
+        {
+            return Ok(());
+        }
+
+        let upgrade = req
+            .header("upgrade")
+            .is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket"));
+        if !upgrade {
+            if let Some(site) = req.header("sec-fetch-site") {
+                return match site.as_str() {
+                    "same-origin" | "none" => Ok(()),
+                    _ => Err(format!("cross-origin request ({site}) was rejected")),
+                };
+            }
+        }
+
+        match origin {
+            // not made by a browser
+            None => Ok(()),
+            Some(origin) if is_own_origin(req, &origin) => Ok(()),
+            Some(origin) => Err(format!("cross-origin request from {origin} was rejected")),
+        }
+    }
+}
+
+/// Whether `origin` has the same scheme and host as the app that received the request.
+fn is_own_origin<R: crate::request::Req<NoCustomError>>(req: &R, origin: &str) -> bool {
+    let (Some((scheme, host)), Some(own_host)) = (origin.split_once("://"), req.header("host"))
+    else {
+        return false;
+    };
+    if !host.eq_ignore_ascii_case(&own_host) {
+        return false;
+    }
+    match req.header("x-forwarded-proto") {
+        // set by a proxy, which may list the scheme of each proxy in turn
+        Some(proto) => proto
+            .split(',')
+            .next()
+            .is_some_and(|proto| proto.trim().eq_ignore_ascii_case(scheme)),
+        None => scheme == "https" || (scheme == "http" && is_loopback(host)),
+    }
+}
+
+/// Whether the host, which may include a port, is this machine.
+fn is_loopback(host: &str) -> bool {
+    let name = match host.rsplit_once(':') {
+        Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) => name,
+        _ => host,
+    };
+    matches!(name, "localhost" | "127.0.0.1" | "[::1]")
+}
+
+impl<Req, Res> Layer<Req, Res> for CsrfLayer
+where
+    Req: crate::request::Req<NoCustomError> + Send + 'static,
+    Res: crate::response::Res<NoCustomError> + Send + 'static,
+{
+    fn layer(&self, inner: BoxedService<Req, Res>) -> BoxedService<Req, Res> {
+        BoxedService::new(Csrf {
+            layer: self.clone(),
+            inner,
+            ty: PhantomData,
+        })
+    }
+}
+
+struct Csrf<Req, Res> {
+    layer: CsrfLayer,
+    inner: BoxedService<Req, Res>,
+    ty: PhantomData<fn(Req) -> Res>,
+}
+
+impl<Req, Res> Service<Req, Res> for Csrf<Req, Res>
+where
+    Req: crate::request::Req<NoCustomError> + Send + 'static,
+    Res: crate::response::Res<NoCustomError> + Send + 'static,
+{
+    fn run(&self, req: Req) -> Pin<Box<dyn Future<Output = Res> + Send>> {
+        match self.layer.verify(&req) {
+            Ok(()) => self.inner.0.run(req),
+            Err(reason) => {
+                let res = metrics::error_response(ServerFnError::Forbidden(reason));
+                Box::pin(async move { res })
+            }
+        }
+    }
+}
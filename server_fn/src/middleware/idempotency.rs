@@ -0,0 +1,445 @@
+//! Running retried mutations only once.
+//!
+//! [`IdempotencyLayer`] looks for an [`IDEMPOTENCY_KEY_HEADER`] on each `POST`, which is
+//! added by [`IdempotentClient`](crate::idempotency::IdempotentClient). The first call with a
+//! key runs as usual, and its response is kept in an [`IdempotencyStore`]. Later calls from
+//! the same client with the same key and the same arguments get the stored response, with an
+//! [`IDEMPOTENT_REPLAY_HEADER`], without running the server function again. A call that
+//! reuses a key with different arguments, or while the first call with it is still running,
+//! is rejected with `409 Conflict` and a [`ServerFnError::Conflict`]. A running call only holds
+//! its key for [`IdempotencyLayer::lease`], so a call that never finishes, for example because
+//! the server crashed, doesn't block retries for as long as responses are stored.
+//!
+//! Keys are scoped to the client that sent them, identified by a [`ClientKey`] as in
+//! [rate limiting](super::rate_limit), so one client can't get the responses stored for
+//! another. By default this is the IP address, which axum only knows if the app is served
+//! with `into_make_service_with_connect_info::<SocketAddr>()`. Calls with a key from a client
+//! that can't be identified are rejected with [`ServerFnError::UnidentifiedClient`], and with
+//! the `tracing` feature, the first of them for each layer is logged as an error.
+//!
+//! Calls without a key, and calls with other methods, are not affected. Responses with a
+//! `5xx`, `401`, `403` or `429` status, streaming responses, and responses larger than
+//! [`IdempotencyLayer::max_body`] are not stored, so a call that gets one of those can be
+//! retried with the same key. The `Set-Cookie` headers of a response are never stored.
+//!
+//! ```ignore
+//! use server_fns::middleware::idempotency::IdempotencyLayer;
+//!
+//! server_fns::axum::register_middleware(IdempotencyLayer::new());
+//! ```
+//!
+//! This is currently only available with the `axum` integration.
+
+use super::rate_limit::unidentified_client;
+pub use super::rate_limit::{ClientKey, Header, RemoteAddr};
+use super::{metrics, BoxedService, Layer, Service};
+pub use crate::idempotency::IDEMPOTENCY_KEY_HEADER;
+use crate::{
+    error::{NoCustomError, ServerFnError},
+    request::{limit_body, BodyLimit, Req},
+};
+use axum::body::{Body, HttpBody};
+use dashmap::{mapref::entry::Entry, DashMap};
+use http::{header::SET_COOKIE, HeaderName, HeaderValue, Method, Request, Response, StatusCode};
+use http_body_util::BodyExt;
+use serde::{Deserialize, Serialize};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// The header added to a response that was stored for an earlier call with the same key.
+pub const IDEMPOTENT_REPLAY_HEADER: &str = "idempotent-replayed";
+
+/// How long responses are stored, unless it is set with [`IdempotencyLayer::ttl`].
+pub const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long a running call holds its key, unless it is set with [`IdempotencyLayer::lease`].
+pub const DEFAULT_LEASE: Duration = Duration::from_secs(60);
+
+/// The largest request or response body that is buffered, in bytes, unless it is set with
+/// [`IdempotencyLayer::max_body`].
+pub const DEFAULT_MAX_BODY: u64 = 1024 * 1024;
+
+/// A response that has been stored for an idempotency key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredResponse {
+    /// Identifies the arguments of the call that the response is for.
+    pub fingerprint: String,
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// What has already happened with an idempotency key, returned by
+/// [`IdempotencyStore::claim`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Claim {
+    /// The key had not been used, and now belongs to this call.
+    Claimed,
+    /// Another call with the key is still running.
+    InProgress,
+    /// A call with the key has finished, with this response.
+    Completed(StoredResponse),
+}
+
+/// Stores the state of every idempotency key.
+///
+/// [`MemoryStore`] keeps them in the memory of the current process. Implement this trait to
+/// share them between several servers, for example in Redis.
+pub trait IdempotencyStore: Send + Sync + 'static {
+    /// Claims `key` for a new call, unless it has already been claimed.
+    ///
+    /// The claim expires after `lease`, unless the call completes or releases it first, so
+    /// that a call that never finishes doesn't hold the key until it would have expired.
+    fn claim(&self, key: &str, lease: Duration) -> impl Future<Output = Claim> + Send;
+
+    /// Stores the response to the call that claimed `key`, for `ttl`.
+    fn complete(
+        &self,
+        key: &str,
+        response: StoredResponse,
+        ttl: Duration,
+    ) -> impl Future<Output = ()> + Send;
+
+    /// Releases `key` without storing a response, so that the call can be made again.
+    fn release(&self, key: &str) -> impl Future<Output = ()> + Send;
+}
+
+/// Keeps idempotency keys in memory. This is the default [`IdempotencyStore`].
+#[derive(Default)]
+pub struct MemoryStore {
+    keys: DashMap<String, (Option<StoredResponse>, Instant)>,
+    calls: AtomicUsize,
+}
+
+impl MemoryStore {
+    /// Creates an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl IdempotencyStore for MemoryStore {
+    fn claim(&self, key: &str, lease: Duration) -> impl Future<Output = Claim> + Send {
+        let now = Instant::now();
+        if self.calls.fetch_add(1, Ordering::Relaxed) % 1024 == 1023 {
+            self.keys.retain(|_, (_, expires)| *expires > now);
+        }
+        let claim = match self.keys.entry(key.to_string()) {
+            Entry::Occupied(entry) if entry.get().1 > now => match &entry.get().0 {
+                Some(response) => Claim::Completed(response.clone()),
+                None => Claim::InProgress,
+            },
+            Entry::Occupied(mut entry) => {
+                entry.insert((None, now + lease));
+                Claim::Claimed
+            }
+            Entry::Vacant(entry) => {
+                entry.insert((None, now + lease));
+                Claim::Claimed
+            }
+        };
+        async move { claim }
+    }
+
+    fn complete(
+        &self,
+        key: &str,
+        response: StoredResponse,
+        ttl: Duration,
+    ) -> impl Future<Output = ()> + Send {
+        self.keys
+            .insert(key.to_string(), (Some(response), Instant::now() + ttl));
+        async {}
+    }
+
+    fn release(&self, key: &str) -> impl Future<Output = ()> + Send {
+        self.keys.remove(key);
+        async {}
+    }
+}
+
+/// Middleware that runs each `POST` with the same idempotency key only once.
+///
+/// See the [module documentation](self) for details.
+pub struct IdempotencyLayer<K = RemoteAddr, S = MemoryStore> {
+    key: Arc<K>,
+    store: Arc<S>,
+    ttl: Duration,
+    lease: Duration,
+    max_body: u64,
+    unidentified: Arc<AtomicBool>,
+}
+
+impl IdempotencyLayer {
+    /// Scopes keys to each IP address, and stores responses in memory for [`DEFAULT_TTL`].
+    pub fn new() -> Self {
+        Self {
+            key: Arc::new(RemoteAddr),
+            store: Arc::new(MemoryStore::new()),
+            ttl: DEFAULT_TTL,
+            lease: DEFAULT_LEASE,
+            max_body: DEFAULT_MAX_BODY,
+            unidentified: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl Default for IdempotencyLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, S> IdempotencyLayer<K, S> {
+    /// Sets how clients are identified.
+    pub fn key_by<K2>(self, key: K2) -> IdempotencyLayer<K2, S> {
+        IdempotencyLayer {
+            key: Arc::new(key),
+            store: self.store,
+            ttl: self.ttl,
+            lease: self.lease,
+            max_body: self.max_body,
+            unidentified: self.unidentified,
+        }
+    }
+
+    /// Sets where responses are stored.
+    pub fn store<S2>(self, store: S2) -> IdempotencyLayer<K, S2> {
+        IdempotencyLayer {
+            key: self.key,
+            store: Arc::new(store),
+            ttl: self.ttl,
+            lease: self.lease,
+            max_body: self.max_body,
+            unidentified: self.unidentified,
+        }
+    }
+
+    /// Sets how long responses are stored, after which the key can be used again.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Sets how long a running call holds its key, after which a retry with the same key
+    /// runs the server function again. This is [`DEFAULT_LEASE`] by default, and should be
+    /// longer than the server function takes to run.
+    pub fn lease(mut self, lease: Duration) -> Self {
+        self.lease = lease;
+        self
+    }
+
+    /// Sets the largest response that is stored, in bytes, which is also the largest request
+    /// that is accepted if the server function has no body limit. This is
+    /// [`DEFAULT_MAX_BODY`] by default.
+    pub fn max_body(mut self, max_body: u64) -> Self {
+        self.max_body = max_body;
+        self
+    }
+}
+
+impl<K, S> Layer<Request<Body>, Response<Body>> for IdempotencyLayer<K, S>
+where
+    K: ClientKey<Request<Body>>,
+    S: IdempotencyStore,
+{
+    fn layer(
+        &self,
+        inner: BoxedService<Request<Body>, Response<Body>>,
+    ) -> BoxedService<Request<Body>, Response<Body>> {
+        BoxedService::new(Idempotency {
+            key: Arc::clone(&self.key),
+            store: Arc::clone(&self.store),
+            ttl: self.ttl,
+            lease: self.lease,
+            max_body: self.max_body,
+            unidentified: Arc::clone(&self.unidentified),
+            inner,
+        })
+    }
+}
+
+struct Idempotency<K, S> {
+    key: Arc<K>,
+    store: Arc<S>,
+    ttl: Duration,
+    lease: Duration,
+    max_body: u64,
+    unidentified: Arc<AtomicBool>,
+    inner: BoxedService<Request<Body>, Response<Body>>,
+}
+
+impl<K, S> Service<Request<Body>, Response<Body>> for Idempotency<K, S>
+where
+    K: ClientKey<Request<Body>>,
+    S: IdempotencyStore,
+{
+    fn run(&self, req: Request<Body>) -> Pin<Box<dyn Future<Output = Response<Body>> + Send>> {
+        let inner = self.inner.clone();
+        let key = req
+            .headers()
+            .get(IDEMPOTENCY_KEY_HEADER)
+            .and_then(|key| key.to_str().ok())
+            .map(String::from);
+        let Some(key) = key.filter(|_| req.method() == Method::POST) else {
+            return inner.0.run(req);
+        };
+        let Some(client) = self.key.key(&req) else {
+            let err = unidentified_client("idempotency", req.uri().path(), &self.unidentified);
+            return Box::pin(async { metrics::error_response(err) });
+        };
+        let key = format!("{} {client} {key}", req.uri().path());
+        let store = Arc::clone(&self.store);
+        let ttl = self.ttl;
+        let lease = self.lease;
+        let max_body = self.max_body;
+
+        Box::pin(async move {
+            // the body has to be buffered, so it needs a limit even if the server function
+            // doesn't have one
+            let req = if req.extensions().get::<BodyLimit>().is_some() {
+                req
+            } else {
+                match limit_body::<_, NoCustomError>(req, max_body) {
+                    Ok(req) => req,
+                    Err(err) => return metrics::error_response(err),
+                }
+            };
+            // the body is read here to compare the arguments, so it is put back afterwards
+            let (parts, body) = req.into_parts();
+            let body = match Req::<NoCustomError>::try_into_bytes(Request::from_parts(
+                parts.clone(),
+                body,
+            ))
+            .await
+            {
+                Ok(body) => body,
+                Err(err) => return metrics::error_response(err),
+            };
+            let fingerprint = fingerprint(&parts.uri.to_string(), &body);
+            let req = Request::from_parts(parts, Body::from(body));
+
+            match store.claim(&key, lease).await {
+                Claim::Claimed => {}
+                Claim::InProgress => {
+                    return metrics::error_response(ServerFnError::<NoCustomError>::Conflict(
+                        "a call with this idempotency key is still running".into(),
+                    ))
+                }
+                Claim::Completed(stored) if stored.fingerprint != fingerprint => {
+                    return metrics::error_response(ServerFnError::<NoCustomError>::Conflict(
+                        "this idempotency key was already used with different arguments".into(),
+                    ))
+                }
+                Claim::Completed(stored) => return replay(stored),
+            }
+
+            let mut claimed = Claimed {
+                store: Some(Arc::clone(&store)),
+                key: key.clone(),
+            };
+            let res = inner.0.run(req).await;
+            let status = res.status();
+            if status.is_server_error()
+                || status == StatusCode::UNAUTHORIZED
+                || status == StatusCode::FORBIDDEN
+                || status == StatusCode::TOO_MANY_REQUESTS
+                || res
+                    .body()
+                    .size_hint()
+                    .exact()
+                    .is_none_or(|size| size > max_body)
+            {
+                claimed.release().await;
+                return res;
+            }
+
+            let (parts, body) = res.into_parts();
+            let body = match body.collect().await {
+                Ok(body) => body.to_bytes(),
+                Err(e) => {
+                    claimed.release().await;
+                    return metrics::error_response(ServerFnError::<NoCustomError>::Response(
+                        e.to_string(),
+                    ));
+                }
+            };
+            // cookies belong to the call that set them, so they are not replayed
+            let headers = parts
+                .headers
+                .iter()
+                .filter(|(name, _)| **name != SET_COOKIE)
+                .filter_map(|(name, value)| {
+                    Some((name.to_string(), value.to_str().ok()?.to_string()))
+                })
+                .collect();
+            claimed.store = None;
+            let stored = StoredResponse {
+                fingerprint,
+                status: parts.status.as_u16(),
+                headers,
+                body: body.to_vec(),
+            };
+            store.complete(&key, stored, ttl).await;
+            Response::from_parts(parts, Body::from(body))
+        })
+    }
+}
+
+/// Identifies the arguments of a call by its URI and body.
+fn fingerprint(uri: &str, body: &[u8]) -> String {
+    let mut data = Vec::with_capacity(uri.len() + 1 + body.len());
+    data.extend_from_slice(uri.as_bytes());
+    data.push(b'\n');
+    data.extend_from_slice(body);
+    format!("{:016x}", xxhash_rust::const_xxh64::xxh64(&data, 0))
+}
+
+/// Rebuilds a stored response.
+fn replay(stored: StoredResponse) -> Response<Body> {
+    let mut res = Response::new(Body::from(stored.body));
+    *res.status_mut() = StatusCode::from_u16(stored.status).unwrap_or(StatusCode::OK);
+    for (name, value) in stored.headers {
+        if let (Ok(name), Ok(value)) = (HeaderName::try_from(name), HeaderValue::try_from(value)) {
+            if name != SET_COOKIE {
+                res.headers_mut().append(name, value);
+            }
+        }
+    }
+    res.headers_mut().insert(
+        HeaderName::from_static(IDEMPOTENT_REPLAY_HEADER),
+        HeaderValue::from_static("true"),
+    );
+    res
+}
+
+/// Releases a claimed key if the call is dropped before it has finished, so that it can be
+/// retried.
+struct Claimed<S: IdempotencyStore> {
+    store: Option<Arc<S>>,
+    key: String,
+}
+
+impl<S: IdempotencyStore> Claimed<S> {
+    async fn release(&mut self) {
+        if let Some(store) = self.store.take() {
+            store.release(&self.key).await;
+        }
+    }
+}
+
+impl<S: IdempotencyStore> Drop for Claimed<S> {
+    fn drop(&mut self) {
+        if let Some(store) = self.store.take() {
+            let key = std::mem::take(&mut self.key);
+            tokio::spawn(async move { store.release(&key).await });
+        }
+    }
+}
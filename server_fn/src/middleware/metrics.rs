@@ -0,0 +1,337 @@
+//! Prometheus-style metrics for server functions.
+//!
+//! [`MetricsLayer`] records the following for each server function, labelled by its path:
+//!
+//! - `server_fn_calls_total`: the number of calls
+//! - `server_fn_errors_total`: the number of calls that failed, also labelled by the `kind`
+//!   of [`ServerFnError`] (see [`ServerFnError::kind`])
+//! - `server_fn_latency_seconds`: a histogram of how long calls took
+//! - `server_fn_request_bytes` and `server_fn_response_bytes`: histograms of the sizes of
+//!   request and response bodies, when they are known in advance
+//!
+//! The metrics are kept in a [`Metrics`] registry, which can be rendered in the Prometheus
+//! text format. The server integrations provide a `handle_metrics` handler that serves
+//! [`Metrics::global`], to be mounted next to `handle_server_fn`:
+//!
+//! ```ignore
+//! use server_fns::middleware::metrics::MetricsLayer;
+//!
+//! server_fns::axum::register_middleware(MetricsLayer::new());
+//!
+//! let app = Router::new()
+//!     .route("/api/*fn_name", post(server_fns::axum::handle_server_fn))
+//!     .route("/metrics", get(server_fns::axum::handle_metrics));
+//! ```
+//!
+//! Errors are only counted if they are returned by the server function or by middleware
+//! inside the [`MetricsLayer`], so it should usually be registered before any other global
+//! middleware.
+
+use super::{BoxedService, Layer, Service};
+use crate::{
+    context::{use_context, ServerContext},
+    error::{NoCustomError, ServerFnError},
+    response::Res,
+};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// The content type of [`Metrics::render`].
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+const SIZE_BUCKETS: &[f64] = &[
+    64.0, 256.0, 1024.0, 4096.0, 16384.0, 65536.0, 262144.0, 1048576.0, 4194304.0,
+];
+
+static GLOBAL: Lazy<Arc<Metrics>> = Lazy::new(Default::default);
+
+/// The metrics recorded for every server function.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    server_fns: DashMap<String, ServerFnMetrics>,
+}
+
+#[derive(Debug, Clone)]
+struct ServerFnMetrics {
+    calls: u64,
+    errors: BTreeMap<&'static str, u64>,
+    latency: Histogram,
+    request_bytes: Histogram,
+    response_bytes: Histogram,
+}
+
+/// The name and help text of each histogram, in the order of [`ServerFnMetrics::histograms`].
+const HISTOGRAMS: [(&str, &str); 3] = [
+    (
+        "server_fn_latency_seconds",
+        "Server function latency in seconds.",
+    ),
+    (
+        "server_fn_request_bytes",
+        "Size of server function request bodies in bytes.",
+    ),
+    (
+        "server_fn_response_bytes",
+        "Size of server function response bodies in bytes.",
+    ),
+];
+
+impl ServerFnMetrics {
+    fn histograms(&self) -> [&Histogram; 3] {
+        [&self.latency, &self.request_bytes, &self.response_bytes]
+    }
+}
+
+impl Default for ServerFnMetrics {
+    fn default() -> Self {
+        Self {
+            calls: 0,
+            errors: BTreeMap::new(),
+            latency: Histogram::new(LATENCY_BUCKETS),
+            request_bytes: Histogram::new(SIZE_BUCKETS),
+            response_bytes: Histogram::new(SIZE_BUCKETS),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Histogram {
+    bounds: &'static [f64],
+    // not cumulative, unlike the rendered buckets
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        if let Some(bucket) = self.bounds.iter().position(|bound| value <= *bound) {
+            self.counts[bucket] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, path: &str) {
+        let mut cumulative = 0;
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "{name}_bucket{{path=\"{path}\",le=\"{bound}\"}} {cumulative}"
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{name}_bucket{{path=\"{path}\",le=\"+Inf\"}} {}",
+            self.count
+        );
+        let _ = writeln!(out, "{name}_sum{{path=\"{path}\"}} {}", self.sum);
+        let _ = writeln!(out, "{name}_count{{path=\"{path}\"}} {}", self.count);
+    }
+}
+
+/// A finished call, to be recorded.
+struct Call {
+    latency: Duration,
+    request_bytes: Option<u64>,
+    response_bytes: Option<u64>,
+    error: Option<&'static str>,
+}
+
+impl Metrics {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The registry used by [`MetricsLayer::new`] and served by the integrations'
+    /// `handle_metrics`.
+    pub fn global() -> Arc<Metrics> {
+        Arc::clone(&GLOBAL)
+    }
+
+    fn record(&self, path: String, call: Call) {
+        let mut metrics = self.server_fns.entry(path).or_default();
+        metrics.calls += 1;
+        if let Some(kind) = call.error {
+            *metrics.errors.entry(kind).or_default() += 1;
+        }
+        metrics.latency.observe(call.latency.as_secs_f64());
+        if let Some(size) = call.request_bytes {
+            metrics.request_bytes.observe(size as f64);
+        }
+        if let Some(size) = call.response_bytes {
+            metrics.response_bytes.observe(size as f64);
+        }
+    }
+
+    /// Renders the metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        // copied out so that no locks are held while rendering
+        let mut server_fns = self
+            .server_fns
+            .iter()
+            .map(|entry| (escape(entry.key()), entry.value().clone()))
+            .collect::<Vec<_>>();
+        server_fns.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut out = String::new();
+        out.push_str("# HELP server_fn_calls_total Number of server function calls.\n");
+        out.push_str("# TYPE server_fn_calls_total counter\n");
+        for (path, metrics) in &server_fns {
+            let _ = writeln!(
+                out,
+                "server_fn_calls_total{{path=\"{path}\"}} {}",
+                metrics.calls
+            );
+        }
+        out.push_str("# HELP server_fn_errors_total Number of failed server function calls.\n");
+        out.push_str("# TYPE server_fn_errors_total counter\n");
+        for (path, metrics) in &server_fns {
+            for (kind, count) in &metrics.errors {
+                let _ = writeln!(
+                    out,
+                    "server_fn_errors_total{{path=\"{path}\",kind=\"{kind}\"}} {count}"
+                );
+            }
+        }
+        for (index, (name, help)) in HISTOGRAMS.into_iter().enumerate() {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} histogram");
+            for (path, metrics) in &server_fns {
+                metrics.histograms()[index].render(&mut out, name, path);
+            }
+        }
+        out
+    }
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Where the kind of error returned by a call is reported, in the call's context.
+#[derive(Clone, Default)]
+struct ReportedError(Arc<Mutex<Option<&'static str>>>);
+
+/// Turns an error that a server function or middleware failed with into a response, and
+/// reports its kind to the [`MetricsLayer`] around the call, if there is one.
+///
+/// This is called where the error is raised, rather than by [`Res::error_response`] itself,
+/// so that responses built outside of a call, like those of mocks, aren't reported.
+pub(crate) fn error_response<R, CustErr>(err: ServerFnError<CustErr>) -> R
+where
+    R: Res<CustErr>,
+{
+    if let Some(reported) = use_context::<ReportedError>() {
+        *reported.0.lock().expect("metrics lock poisoned") = Some(err.kind());
+    }
+    R::error_response(err)
+}
+
+/// Middleware that records [`Metrics`] for each call.
+///
+/// See the [module documentation](self) for the metrics it records.
+#[derive(Debug, Clone)]
+pub struct MetricsLayer {
+    metrics: Arc<Metrics>,
+}
+
+impl MetricsLayer {
+    /// Records metrics in [`Metrics::global`].
+    pub fn new() -> Self {
+        Self::with_metrics(Metrics::global())
+    }
+
+    /// Records metrics in the given registry.
+    pub fn with_metrics(metrics: Arc<Metrics>) -> Self {
+        Self { metrics }
+    }
+}
+
+impl Default for MetricsLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Req, Res> Layer<Req, Res> for MetricsLayer
+where
+    Req: crate::request::Req<NoCustomError> + Send + 'static,
+    Res: crate::response::Res<NoCustomError> + Send + 'static,
+{
+    fn layer(&self, inner: BoxedService<Req, Res>) -> BoxedService<Req, Res> {
+        BoxedService::new(Record {
+            metrics: Arc::clone(&self.metrics),
+            inner,
+            ty: PhantomData,
+        })
+    }
+}
+
+struct Record<Req, Res> {
+    metrics: Arc<Metrics>,
+    inner: BoxedService<Req, Res>,
+    ty: PhantomData<fn(Req) -> Res>,
+}
+
+impl<Req, Res> Service<Req, Res> for Record<Req, Res>
+where
+    Req: crate::request::Req<NoCustomError> + Send + 'static,
+    Res: crate::response::Res<NoCustomError> + Send + 'static,
+{
+    fn run(&self, req: Req) -> Pin<Box<dyn Future<Output = Res> + Send>> {
+        let path = req.path().to_string();
+        let request_bytes = req
+            .header("content-length")
+            .and_then(|size| size.parse().ok());
+        let metrics = Arc::clone(&self.metrics);
+        let inner = self.inner.clone();
+        let reported = ReportedError::default();
+        let context = ServerContext::current()
+            .map(|context| context.child())
+            .unwrap_or_default();
+        context.insert(reported.clone());
+        let start = Instant::now();
+        Box::pin(context.scope(async move {
+            let res = inner.0.run(req).await;
+            let error = *reported.0.lock().expect("metrics lock poisoned");
+            metrics.record(
+                path,
+                Call {
+                    latency: start.elapsed(),
+                    request_bytes,
+                    response_bytes: res.content_length(),
+                    error,
+                },
+            );
+            res
+        }))
+    }
+}
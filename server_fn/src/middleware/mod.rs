@@ -1,47 +1,146 @@
-use std::{future::Future, pin::Pin};
+#[cfg(any(feature = "axum", feature = "actix"))]
+pub mod concurrency;
+pub mod csrf;
+#[cfg(feature = "axum")]
+pub mod idempotency;
+pub mod metrics;
+pub mod rate_limit;
+#[cfg(any(feature = "axum", feature = "actix"))]
+pub mod timeout;
+
+use std::{future::Future, marker::PhantomData, pin::Pin, sync::Arc};
 
 pub trait Layer<Req, Res>: Send + Sync + 'static {
     fn layer(&self, inner: BoxedService<Req, Res>) -> BoxedService<Req, Res>;
 }
 
-pub struct BoxedService<Req, Res>(pub Box<dyn Service<Req, Res> + Send>);
+/// A type-erased [`Service`], usually a server function wrapped in its middleware.
+///
+/// Cloning it only clones an [`Arc`], so the same stack can be shared by every request.
+pub struct BoxedService<Req, Res>(pub Arc<dyn Service<Req, Res> + Send + Sync>);
 
 impl<Req, Res> BoxedService<Req, Res> {
-    pub fn new(service: impl Service<Req, Res> + Send + 'static) -> Self {
-        Self(Box::new(service))
+    pub fn new(service: impl Service<Req, Res> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(service))
+    }
+}
+
+impl<Req, Res> Clone for BoxedService<Req, Res> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
     }
 }
 
 pub trait Service<Request, Response> {
-    fn run(&mut self, req: Request) -> Pin<Box<dyn Future<Output = Response> + Send>>;
+    fn run(&self, req: Request) -> Pin<Box<dyn Future<Output = Response> + Send>>;
+}
+
+/// Creates middleware from an async function that receives the request and a [`Next`]
+/// handle, which runs the rest of the middleware stack and the server function itself.
+///
+/// Because it only relies on this crate's [`Service`] abstraction, the same function can be
+/// used as middleware with any server integration.
+///
+/// ```ignore
+/// #[server]
+/// #[middleware(server_fns::middleware::from_fn(|req, next| async move {
+///     println!("before the server function");
+///     let res = next.run(req).await;
+///     println!("after the server function");
+///     res
+/// }))]
+/// pub async fn my_server_fn() -> Result<(), ServerFnError> {
+///     Ok(())
+/// }
+/// ```
+pub fn from_fn<F, Fut, Req, Res>(f: F) -> FromFnLayer<F, Req, Res>
+where
+    F: Fn(Req, Next<Req, Res>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Res> + Send + 'static,
+{
+    FromFnLayer {
+        f: Arc::new(f),
+        ty: PhantomData,
+    }
+}
+
+/// Middleware created from an async function with [`from_fn`].
+pub struct FromFnLayer<F, Req, Res> {
+    f: Arc<F>,
+    ty: PhantomData<fn(Req) -> Res>,
+}
+
+impl<F, Fut, Req, Res> Layer<Req, Res> for FromFnLayer<F, Req, Res>
+where
+    F: Fn(Req, Next<Req, Res>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Res> + Send + 'static,
+    Req: 'static,
+    Res: 'static,
+{
+    fn layer(&self, inner: BoxedService<Req, Res>) -> BoxedService<Req, Res> {
+        BoxedService::new(FromFn {
+            f: Arc::clone(&self.f),
+            inner,
+        })
+    }
+}
+
+struct FromFn<F, Req, Res> {
+    f: Arc<F>,
+    inner: BoxedService<Req, Res>,
+}
+
+impl<F, Fut, Req, Res> Service<Req, Res> for FromFn<F, Req, Res>
+where
+    F: Fn(Req, Next<Req, Res>) -> Fut,
+    Fut: Future<Output = Res> + Send + 'static,
+{
+    fn run(&self, req: Req) -> Pin<Box<dyn Future<Output = Res> + Send>> {
+        let next = Next {
+            inner: self.inner.clone(),
+        };
+        Box::pin((self.f)(req, next))
+    }
+}
+
+/// The rest of the middleware stack, passed to middleware created with [`from_fn`].
+pub struct Next<Req, Res> {
+    inner: BoxedService<Req, Res>,
+}
+
+impl<Req, Res> Next<Req, Res> {
+    /// Passes the request to the rest of the middleware stack, and eventually to the server
+    /// function, returning its response.
+    pub fn run(self, req: Req) -> Pin<Box<dyn Future<Output = Res> + Send>> {
+        self.inner.0.run(req)
+    }
 }
 
 #[cfg(feature = "axum")]
 mod axum {
-    use crate::{response::Res, ServerFnError};
+    use crate::ServerFnError;
     use axum::body::Body;
     use http::{Request, Response};
     use std::fmt::{Debug, Display};
     use std::future::Future;
     use std::pin::Pin;
+    use tower::ServiceExt;
 
     use super::{BoxedService, Service};
 
     impl<S> super::Service<Request<Body>, Response<Body>> for S
     where
-        S: tower::Service<Request<Body>, Response = Response<Body>>,
+        S: tower::Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
         S::Future: Send + 'static,
         S::Error: Into<ServerFnError> + Send + Debug + Display + Sync + 'static,
     {
-        fn run(
-            &mut self,
-            req: Request<Body>,
-        ) -> Pin<Box<dyn Future<Output = Response<Body>> + Send>> {
-            let inner = self.call(req);
+        fn run(&self, req: Request<Body>) -> Pin<Box<dyn Future<Output = Response<Body>> + Send>> {
+            // tower services are called through `&mut self`, so each call gets its own clone
+            let service = self.clone();
             Box::pin(async move {
-                inner.await.unwrap_or_else(|e| {
-                    let err = ServerFnError::from(e);
-                    Response::<Body>::error_response(err)
+                service.oneshot(req).await.unwrap_or_else(|e| {
+                    let err: ServerFnError = e.into();
+                    crate::middleware::metrics::error_response(err)
                 })
             })
         }
@@ -69,39 +168,47 @@ mod axum {
     impl<L> super::Layer<Request<Body>, Response<Body>> for L
     where
         L: tower_layer::Layer<BoxedService<Request<Body>, Response<Body>>> + Sync + Send + 'static,
-        L::Service: Service<Request<Body>, Response<Body>> + Send + 'static,
+        L::Service: Service<Request<Body>, Response<Body>> + Send + Sync + 'static,
     {
         fn layer(
             &self,
             inner: BoxedService<Request<Body>, Response<Body>>,
         ) -> BoxedService<Request<Body>, Response<Body>> {
-            BoxedService(Box::new(self.layer(inner)))
+            BoxedService::new(self.layer(inner))
         }
     }
 }
 
 #[cfg(feature = "actix")]
 mod actix {
+    use super::BoxedService;
     use crate::{
-        response::{actix::ActixResponse, Res},
+        request::actix::ActixRequest,
+        response::actix::ActixResponse,
         ServerFnError,
     };
     use actix_web::{HttpRequest, HttpResponse};
     use std::fmt::{Debug, Display};
     use std::{future::Future, pin::Pin};
 
+    impl super::Service<ActixRequest, ActixResponse> for BoxedService<ActixRequest, ActixResponse> {
+        fn run(&self, req: ActixRequest) -> Pin<Box<dyn Future<Output = ActixResponse> + Send>> {
+            self.0.run(req)
+        }
+    }
+
     impl<S> super::Service<HttpRequest, HttpResponse> for S
     where
         S: actix_web::dev::Service<HttpRequest, Response = HttpResponse>,
         S::Future: Send + 'static,
         S::Error: Into<ServerFnError> + Debug + Display + 'static,
     {
-        fn run(&mut self, req: HttpRequest) -> Pin<Box<dyn Future<Output = HttpResponse> + Send>> {
+        fn run(&self, req: HttpRequest) -> Pin<Box<dyn Future<Output = HttpResponse> + Send>> {
             let inner = self.call(req);
             Box::pin(async move {
                 inner.await.unwrap_or_else(|e| {
-                    let err = ServerFnError::from(e);
-                    ActixResponse::error_response(err).into_inner()
+                    let err: ServerFnError = e.into();
+                    crate::middleware::metrics::error_response::<ActixResponse, _>(err).into_inner()
                 })
             })
         }
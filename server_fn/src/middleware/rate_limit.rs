@@ -0,0 +1,326 @@
+//! Token-bucket rate limiting for server functions.
+//!
+//! Each client gets a bucket of tokens for each server function, keyed by the path of the
+//! function and a [`ClientKey`] taken from the request. Every call takes a token, and tokens
+//! are refilled at a steady rate up to the bucket's capacity. A call that finds the bucket
+//! empty is rejected with `429 Too Many Requests`, a `Retry-After` header, and a
+//! [`ServerFnError::RateLimited`] that the client can use to retry later.
+//!
+//! By default clients are identified by their IP address, which axum only knows if the app
+//! is served with `into_make_service_with_connect_info::<SocketAddr>()`. Calls from a client
+//! that can't be identified are rejected with [`ServerFnError::UnidentifiedClient`], rather
+//! than sharing one bucket with every other such client, so an app served without it rejects
+//! every call. With the `tracing` feature, the first of these calls for each layer is also
+//! logged as an error.
+//!
+//! ```ignore
+//! use server_fns::middleware::rate_limit::{Quota, RateLimitLayer};
+//!
+//! #[server]
+//! #[middleware(RateLimitLayer::new(Quota::per_minute(10)))]
+//! pub async fn send_message(text: String) -> Result<(), ServerFnError> {
+//!     todo!()
+//! }
+//! ```
+
+use super::{metrics, BoxedService, Layer, Service};
+use crate::{
+    error::{NoCustomError, ServerFnError},
+    request::Req,
+};
+use dashmap::DashMap;
+use std::{
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// The size of a token bucket and the rate at which it refills.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quota {
+    capacity: u32,
+    refill: Duration,
+}
+
+impl Quota {
+    /// Allows `n` calls per second, all of which can be made at once.
+    pub fn per_second(n: u32) -> Self {
+        Self::with_period(n, Duration::from_secs(1))
+    }
+
+    /// Allows `n` calls per minute, all of which can be made at once.
+    pub fn per_minute(n: u32) -> Self {
+        Self::with_period(n, Duration::from_secs(60))
+    }
+
+    /// Allows `n` calls per hour, all of which can be made at once.
+    pub fn per_hour(n: u32) -> Self {
+        Self::with_period(n, Duration::from_secs(60 * 60))
+    }
+
+    /// Allows `n` calls in each `period`, all of which can be made at once.
+    pub fn with_period(n: u32, period: Duration) -> Self {
+        let n = n.max(1);
+        Self {
+            capacity: n,
+            refill: period / n,
+        }
+    }
+
+    /// Sets how many calls can be made at once, without changing the rate at which the
+    /// bucket refills.
+    pub fn burst(mut self, capacity: u32) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// The number of calls that can be made at once.
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// The time it takes to refill one token.
+    pub fn refill(&self) -> Duration {
+        self.refill
+    }
+}
+
+/// Stores the state of every token bucket.
+///
+/// [`MemoryStore`] keeps the buckets in the memory of the current process. Implement this
+/// trait to share them between several servers, for example in Redis.
+pub trait RateLimitStore: Send + Sync + 'static {
+    /// Takes a token from the bucket with the given key, or returns how long to wait until
+    /// one will be available if the bucket is empty.
+    fn take(&self, key: &str, quota: Quota) -> impl Future<Output = Result<(), Duration>> + Send;
+}
+
+/// Keeps token buckets in memory. This is the default [`RateLimitStore`].
+#[derive(Default)]
+pub struct MemoryStore {
+    buckets: DashMap<String, Bucket>,
+    calls: AtomicUsize,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    quota: Quota,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() / self.quota.refill.as_secs_f64())
+            .min(self.quota.capacity as f64);
+        self.updated = now;
+    }
+}
+
+impl MemoryStore {
+    /// Creates an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // buckets that have refilled completely are the same as missing ones, so they can be
+    // dropped to stop the map growing with every client that has ever made a call
+    fn prune(&self, now: Instant) {
+        self.buckets.retain(|_, bucket| {
+            bucket.refill(now);
+            bucket.tokens < bucket.quota.capacity as f64
+        });
+    }
+}
+
+impl RateLimitStore for MemoryStore {
+    fn take(&self, key: &str, quota: Quota) -> impl Future<Output = Result<(), Duration>> + Send {
+        let now = Instant::now();
+        if self.calls.fetch_add(1, Ordering::Relaxed) % 1024 == 1023 {
+            self.prune(now);
+        }
+        let mut bucket = self.buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: quota.capacity as f64,
+            updated: now,
+            quota,
+        });
+        bucket.refill(now);
+        bucket.quota = quota;
+        let res = if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(quota.refill.mul_f64(1.0 - bucket.tokens))
+        };
+        async move { res }
+    }
+}
+
+/// Identifies the client that made a request, so that each client has its own buckets.
+///
+/// This is implemented for [`RemoteAddr`], [`Header`], and any
+/// `Fn(&Req) -> Option<String>`, which can read anything from the request, like the id of a
+/// user that an earlier middleware added to its extensions. Requests for which the key is
+/// `None` are rejected with a [`ServerFnError::UnidentifiedClient`], so that leaving out
+/// whatever the key is taken from can't be used to get around the limit.
+pub trait ClientKey<Req>: Send + Sync + 'static {
+    /// Returns the key for the client that made the request.
+    fn key(&self, req: &Req) -> Option<String>;
+}
+
+impl<F, Req> ClientKey<Req> for F
+where
+    F: Fn(&Req) -> Option<String> + Send + Sync + 'static,
+{
+    fn key(&self, req: &Req) -> Option<String> {
+        self(req)
+    }
+}
+
+/// The error for a call whose client can't be identified by `middleware`, which is logged
+/// the first time that it happens for each layer, since it usually means that the app isn't
+/// served the way that the [`ClientKey`] needs.
+pub(crate) fn unidentified_client(
+    middleware: &str,
+    path: &str,
+    logged: &AtomicBool,
+) -> ServerFnError<NoCustomError> {
+    let message = format!("{middleware} found no client key in a call to {path}");
+    if !logged.swap(true, Ordering::Relaxed) {
+        #[cfg(feature = "tracing")]
+        tracing::error!("{message}; check that the app provides what the client key reads");
+    }
+    ServerFnError::UnidentifiedClient(message)
+}
+
+/// Identifies clients by their IP address.
+///
+/// With axum, this requires serving the app with
+/// `into_make_service_with_connect_info::<SocketAddr>()`, or else every call is rejected.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RemoteAddr;
+
+impl<R: Req<NoCustomError>> ClientKey<R> for RemoteAddr {
+    fn key(&self, req: &R) -> Option<String> {
+        req.remote_addr().map(|addr| addr.ip().to_string())
+    }
+}
+
+/// Identifies clients by the value of a header, like an API key or the address set by a
+/// reverse proxy.
+#[derive(Debug, Clone, Copy)]
+pub struct Header(pub &'static str);
+
+impl<R: Req<NoCustomError>> ClientKey<R> for Header {
+    fn key(&self, req: &R) -> Option<String> {
+        req.header(self.0)
+    }
+}
+
+/// Middleware that limits how often each client can call a server function.
+///
+/// It can be added to a single server function with `#[middleware]`, or to every server
+/// function with `register_middleware`, in which case each function still has its own
+/// buckets.
+pub struct RateLimitLayer<K = RemoteAddr, S = MemoryStore> {
+    quota: Quota,
+    key: Arc<K>,
+    store: Arc<S>,
+    unidentified: Arc<AtomicBool>,
+}
+
+impl RateLimitLayer {
+    /// Limits each IP address to the given quota, storing buckets in memory.
+    pub fn new(quota: Quota) -> Self {
+        Self {
+            quota,
+            key: Arc::new(RemoteAddr),
+            store: Arc::new(MemoryStore::new()),
+            unidentified: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl<K, S> RateLimitLayer<K, S> {
+    /// Sets how clients are identified.
+    pub fn key_by<K2>(self, key: K2) -> RateLimitLayer<K2, S> {
+        RateLimitLayer {
+            quota: self.quota,
+            key: Arc::new(key),
+            store: self.store,
+            unidentified: self.unidentified,
+        }
+    }
+
+    /// Sets where the buckets are stored.
+    pub fn store<S2>(self, store: S2) -> RateLimitLayer<K, S2> {
+        RateLimitLayer {
+            quota: self.quota,
+            key: self.key,
+            store: Arc::new(store),
+            unidentified: self.unidentified,
+        }
+    }
+}
+
+impl<K, S, Req, Res> Layer<Req, Res> for RateLimitLayer<K, S>
+where
+    K: ClientKey<Req>,
+    S: RateLimitStore,
+    Req: crate::request::Req<NoCustomError> + Send + 'static,
+    Res: crate::response::Res<NoCustomError> + 'static,
+{
+    fn layer(&self, inner: BoxedService<Req, Res>) -> BoxedService<Req, Res> {
+        BoxedService::new(RateLimit {
+            quota: self.quota,
+            key: Arc::clone(&self.key),
+            store: Arc::clone(&self.store),
+            unidentified: Arc::clone(&self.unidentified),
+            inner,
+            ty: PhantomData,
+        })
+    }
+}
+
+struct RateLimit<K, S, Req, Res> {
+    quota: Quota,
+    key: Arc<K>,
+    store: Arc<S>,
+    unidentified: Arc<AtomicBool>,
+    inner: BoxedService<Req, Res>,
+    ty: PhantomData<fn(Req) -> Res>,
+}
+
+impl<K, S, Req, Res> Service<Req, Res> for RateLimit<K, S, Req, Res>
+where
+    K: ClientKey<Req>,
+    S: RateLimitStore,
+    Req: crate::request::Req<NoCustomError> + Send + 'static,
+    Res: crate::response::Res<NoCustomError> + 'static,
+{
+    fn run(&self, req: Req) -> Pin<Box<dyn Future<Output = Res> + Send>> {
+        let Some(client) = self.key.key(&req) else {
+            let err = unidentified_client("rate limiting", req.path(), &self.unidentified);
+            return Box::pin(async { metrics::error_response(err) });
+        };
+        let key = format!("{} {client}", req.path());
+        let quota = self.quota;
+        let store = Arc::clone(&self.store);
+        let inner = self.inner.clone();
+        Box::pin(async move {
+            match store.take(&key, quota).await {
+                Ok(()) => inner.0.run(req).await,
+                Err(wait) => {
+                    // round up, so that retrying after this long always succeeds
+                    let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+                    metrics::error_response(ServerFnError::RateLimited(secs))
+                }
+            }
+        })
+    }
+}
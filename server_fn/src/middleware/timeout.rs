@@ -0,0 +1,79 @@
+//! Time limits for server functions.
+//!
+//! [`TimeoutLayer`] stops waiting for a server function once it has run for longer than the
+//! given time, and responds with `504 Gateway Timeout` and a [`ServerFnError::Timeout`]
+//! instead. The server function's future is dropped, so it does not keep running in the
+//! background.
+//!
+//! This is usually set with `#[server(timeout = "5s")]`, which accepts a number followed
+//! by `ms`, `s`, `m` or `h`:
+//!
+//! ```ignore
+//! #[server(timeout = "30s")]
+//! pub async fn generate_report(id: u32) -> Result<Report, ServerFnError> {
+//!     todo!()
+//! }
+//! ```
+//!
+//! The time limit only covers the server function itself. Middleware added with
+//! `#[middleware]` runs outside of it, and so does time spent waiting for a
+//! `max_concurrent` slot.
+
+use super::{metrics, BoxedService, Layer, Service};
+use crate::error::{NoCustomError, ServerFnError};
+use std::{future::Future, marker::PhantomData, pin::Pin, time::Duration};
+
+/// Middleware that limits how long a call can take.
+///
+/// See the [module documentation](self) for details.
+#[derive(Debug, Clone, Copy)]
+pub struct TimeoutLayer {
+    duration: Duration,
+}
+
+impl TimeoutLayer {
+    /// Responds with an error to any call that has not finished after `duration`.
+    pub fn new(duration: Duration) -> Self {
+        Self { duration }
+    }
+}
+
+impl<Req, Res> Layer<Req, Res> for TimeoutLayer
+where
+    Req: crate::request::Req<NoCustomError> + Send + 'static,
+    Res: crate::response::Res<NoCustomError> + Send + 'static,
+{
+    fn layer(&self, inner: BoxedService<Req, Res>) -> BoxedService<Req, Res> {
+        BoxedService::new(Timeout {
+            duration: self.duration,
+            inner,
+            ty: PhantomData,
+        })
+    }
+}
+
+struct Timeout<Req, Res> {
+    duration: Duration,
+    inner: BoxedService<Req, Res>,
+    ty: PhantomData<fn(Req) -> Res>,
+}
+
+impl<Req, Res> Service<Req, Res> for Timeout<Req, Res>
+where
+    Req: crate::request::Req<NoCustomError> + Send + 'static,
+    Res: crate::response::Res<NoCustomError> + Send + 'static,
+{
+    fn run(&self, req: Req) -> Pin<Box<dyn Future<Output = Res> + Send>> {
+        let duration = self.duration;
+        let fut = self.inner.0.run(req);
+        Box::pin(async move {
+            match tokio::time::timeout(duration, fut).await {
+                Ok(res) => res,
+                Err(_) => metrics::error_response(ServerFnError::Timeout(format!(
+                    "no response after {}ms",
+                    duration.as_millis()
+                ))),
+            }
+        })
+    }
+}
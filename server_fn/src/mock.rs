@@ -0,0 +1,288 @@
+//! A stand-in server that answers server function calls with canned responses, for working
+//! on the front end without the real backend.
+//!
+//! A [`MockServer`] maps the path of each server function to a response: a fixed output,
+//! a closure that receives the decoded arguments, or the responses in a file recorded with
+//! [`replay`](crate::replay). Outputs and arguments go through the same codecs as the real
+//! server functions, so the app can't tell the difference. It can also add latency and fail
+//! some calls at random, to see how the app copes with a slow or flaky backend.
+//!
+//! Mount it at the same path as [`handle_server_fn`](crate::axum::handle_server_fn):
+//!
+//! ```ignore
+//! use server_fns::{mock::MockServer, replay::Fixtures};
+//!
+//! let mock = MockServer::new()
+//!     .value::<GetUser>(User { id: 1, name: "Ada".into() })
+//!     .handler(|args: AddTodo| async move { Ok(Todo::new(args.title)) })
+//!     .fixtures(Fixtures::load("fixtures.json")?)
+//!     .latency(Duration::from_millis(50), Duration::from_millis(300))
+//!     .error_rate(0.1, || ServerFnError::Unavailable("flaky on purpose".into()))
+//!     .cors(Cors::new().allow_origin("http://localhost:8080"));
+//!
+//! let app = Router::new().route("/api/*fn_name", any(move |req| mock.handle(req)));
+//! ```
+
+use crate::{
+    axum::add_headers,
+    codec::{Encoding, FromReq, IntoRes},
+    context::ServerContext,
+    cors::Cors,
+    error::{NoCustomError, ServerFnError},
+    random::random_u64,
+    redirect::REDIRECT_HEADER,
+    replay::{Fixtures, RecordedCall},
+    response::{Res, ResponseOptions},
+    ServerFn,
+};
+use axum::body::Body;
+use http::{
+    header::{LOCATION, ORIGIN},
+    Method, Request, Response, StatusCode,
+};
+use http_body_util::BodyExt;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Display},
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    time::Duration,
+};
+
+type MockFn = Arc<
+    dyn Fn(Request<Body>) -> Pin<Box<dyn Future<Output = Response<Body>> + Send>> + Send + Sync,
+>;
+
+/// The response for one server function path.
+#[derive(Clone)]
+struct Mock {
+    method: String,
+    respond: MockFn,
+}
+
+#[derive(Clone, Default)]
+struct MockConfig {
+    mocks: HashMap<String, Mock>,
+    latency: Option<(Duration, Duration)>,
+    errors: Option<(f64, Arc<dyn Fn() -> ServerFnError + Send + Sync>)>,
+    cors: Option<Cors>,
+}
+
+/// A server that answers server function calls with canned responses.
+///
+/// See the [module documentation](self) for details.
+#[derive(Clone, Default)]
+pub struct MockServer(Arc<MockConfig>);
+
+impl MockServer {
+    /// Creates a server without any responses.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn config(&mut self) -> &mut MockConfig {
+        Arc::make_mut(&mut self.0)
+    }
+
+    fn mock(mut self, path: &str, method: &str, respond: MockFn) -> Self {
+        let mock = Mock {
+            method: method.to_string(),
+            respond,
+        };
+        self.config().mocks.insert(path.to_string(), mock);
+        self
+    }
+
+    /// Answers every call to the server function `T` with `output`, whatever its arguments.
+    pub fn value<T>(self, output: T::Output) -> Self
+    where
+        T: ServerFn,
+        T::Output: IntoRes<T::Error, Response<Body>, T::OutputEncoding> + Clone + Sync + 'static,
+        T::Error: Send + Sync + Debug + Display + Serialize + 'static,
+    {
+        self.mock(
+            T::PATH,
+            T::InputEncoding::METHOD,
+            Arc::new(move |_| {
+                let output = output.clone();
+                Box::pin(async move {
+                    output
+                        .into_res()
+                        .await
+                        .unwrap_or_else(Res::<T::Error>::error_response)
+                })
+            }),
+        )
+    }
+
+    /// Answers calls to the server function `T` by decoding its arguments and calling `f`
+    /// with them.
+    ///
+    /// `f` runs in a [`ServerContext`] with [`ResponseOptions`], like the body of a server
+    /// function, so it can set the status code and headers of the response.
+    pub fn handler<T, F, Fut>(self, f: F) -> Self
+    where
+        T: ServerFn + FromReq<T::Error, Request<Body>, T::InputEncoding> + 'static,
+        T::Output: IntoRes<T::Error, Response<Body>, T::OutputEncoding>,
+        T::Error: Send + Sync + Debug + Display + Serialize + 'static,
+        F: Fn(T) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T::Output, ServerFnError<T::Error>>> + Send + 'static,
+    {
+        let f = Arc::new(f);
+        self.mock(
+            T::PATH,
+            T::InputEncoding::METHOD,
+            Arc::new(move |req| {
+                let f = Arc::clone(&f);
+                let context = ServerContext::new();
+                let options = ResponseOptions::default();
+                context.insert(options.clone());
+                Box::pin(context.scope(async move {
+                    let res = async {
+                        let args =
+                            <T as FromReq<T::Error, Request<Body>, T::InputEncoding>>::from_req(
+                                req,
+                            )
+                            .await?;
+                        f(args).await?.into_res().await
+                    }
+                    .await;
+                    res.and_then(|res| res.with_options(&options))
+                        .unwrap_or_else(Res::<T::Error>::error_response)
+                }))
+            }),
+        )
+    }
+
+    /// Answers calls with the responses that were recorded with
+    /// [`ReplayClient`](crate::replay::ReplayClient).
+    ///
+    /// A call gets the first recorded response with the same arguments, or an error if there
+    /// is none, like it would from [`ReplayClient`](crate::replay::ReplayClient). Fixtures
+    /// replace any other response for the same server function.
+    pub fn fixtures(mut self, fixtures: Fixtures) -> Self {
+        let mut by_path = HashMap::<String, Vec<RecordedCall>>::new();
+        for call in fixtures.calls {
+            by_path.entry(call.path.clone()).or_default().push(call);
+        }
+        for (path, calls) in by_path {
+            let method = calls[0].method.clone();
+            let calls = Arc::new(calls);
+            self = self.mock(
+                &path,
+                &method,
+                Arc::new(move |req| Box::pin(replay_fixture(Arc::clone(&calls), req))),
+            );
+        }
+        self
+    }
+
+    /// Delays every response by a random duration between `min` and `max`.
+    pub fn latency(mut self, min: Duration, max: Duration) -> Self {
+        self.config().latency = Some((min, max.max(min)));
+        self
+    }
+
+    /// Fails each call with the error returned by `error`, instead of answering it, with the
+    /// probability `rate` (between `0.0` and `1.0`).
+    pub fn error_rate(
+        mut self,
+        rate: f64,
+        error: impl Fn() -> ServerFnError + Send + Sync + 'static,
+    ) -> Self {
+        self.config().errors = Some((rate, Arc::new(error)));
+        self
+    }
+
+    /// Answers CORS preflight requests and adds CORS headers to every response, for an app
+    /// that is served from another origin.
+    pub fn cors(mut self, cors: Cors) -> Self {
+        self.config().cors = Some(cors);
+        self
+    }
+
+    /// Answers a server function call, like [`handle_server_fn`](crate::axum::handle_server_fn).
+    pub fn handle(&self, req: Request<Body>) -> impl Future<Output = Response<Body>> + Send {
+        let config = Arc::clone(&self.0);
+        async move {
+            let origin = req
+                .headers()
+                .get(ORIGIN)
+                .and_then(|origin| origin.to_str().ok())
+                .map(String::from);
+            let mock = config.mocks.get(req.uri().path());
+
+            if let (Some(cors), Some(mock), &Method::OPTIONS) = (&config.cors, mock, req.method()) {
+                let mut res = Response::builder()
+                    .status(StatusCode::NO_CONTENT)
+                    .body(Body::empty())
+                    .unwrap();
+                add_headers(
+                    &mut res,
+                    cors.preflight_headers(origin.as_deref(), &mock.method),
+                );
+                return res;
+            }
+
+            if let Some((min, max)) = config.latency {
+                tokio::time::sleep(min + (max - min).mul_f64(random())).await;
+            }
+
+            let mut res = match (mock, &config.errors) {
+                (_, Some((rate, error))) if random() < *rate => {
+                    Res::<NoCustomError>::error_response(error())
+                }
+                (Some(mock), _) => (mock.respond)(req).await,
+                (None, _) => Res::<NoCustomError>::error_response(ServerFnError::Registration(
+                    format!("the mock server has no response for {}", req.uri().path()),
+                )),
+            };
+            if let Some(cors) = &config.cors {
+                add_headers(&mut res, cors.response_headers(origin.as_deref()));
+            }
+            res
+        }
+    }
+}
+
+/// Answers a call with the first recorded call that matches it.
+async fn replay_fixture(calls: Arc<Vec<RecordedCall>>, req: Request<Body>) -> Response<Body> {
+    let (parts, body) = req.into_parts();
+    let body = body.collect().await.map(|body| body.to_bytes()).ok();
+    let Some(call) = calls.iter().find(|call| {
+        call.method == parts.method.as_str()
+            && call.query.as_deref() == parts.uri.query()
+            && call
+                .body
+                .to_bytes::<()>()
+                .is_ok_and(|recorded| Some(recorded) == body)
+    }) else {
+        return Res::<NoCustomError>::error_response(ServerFnError::Request(format!(
+            "no recorded call to {} matches these arguments",
+            parts.uri.path()
+        )));
+    };
+
+    let recorded = &call.response;
+    let body = match recorded.body.to_bytes::<NoCustomError>() {
+        Ok(body) => body,
+        Err(err) => return Res::<NoCustomError>::error_response(err),
+    };
+    let mut res = Response::builder().status(recorded.status);
+    if let Some(location) = &recorded.location {
+        res = res.header(LOCATION, location);
+    }
+    if recorded.has_redirect {
+        res = res.header(REDIRECT_HEADER, "");
+    }
+    res.body(Body::from(body)).unwrap_or_else(|e| {
+        Res::<NoCustomError>::error_response(ServerFnError::Response(e.to_string()))
+    })
+}
+
+/// A random number between `0.0` and `1.0`.
+fn random() -> f64 {
+    (random_u64() >> 11) as f64 / (1u64 << 53) as f64
+}
@@ -0,0 +1,9 @@
+//! Random numbers for ids and keys, from the operating system or, in the browser, from
+//! `crypto.getRandomValues`.
+
+/// Returns a random `u64`.
+pub(crate) fn random_u64() -> u64 {
+    let mut bytes = [0; 8];
+    getrandom::getrandom(&mut bytes).expect("no random number generator is available");
+    u64::from_le_bytes(bytes)
+}
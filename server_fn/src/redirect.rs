@@ -1,5 +1,21 @@
+use crate::{context::use_context, response::ResponseOptions};
 use std::sync::OnceLock;
 
+/// A custom header that is set on the response when the server function calls [`redirect`].
+///
+/// `fetch` requests follow HTTP redirects silently, so the client uses this header rather
+/// than the status code to know that it should call the redirect hook.
+pub const REDIRECT_HEADER: &str = "serverfnredirect";
+
+/// The query parameter that holds the path of the server function when a plain HTML form
+/// submission is redirected back to the page it came from.
+pub const PATH_QUERY_PARAM: &str = "server_fn_path";
+
+/// The query parameter that holds the JSON-encoded [`ServerFnError`](crate::ServerFnError),
+/// if the server function failed, when a plain HTML form submission is redirected back to
+/// the page it came from.
+pub const ERROR_QUERY_PARAM: &str = "server_fn_error";
+
 static REDIRECT_HOOK: OnceLock<Box<dyn Fn(&str) + Send + Sync>> = OnceLock::new();
 
 pub fn set_redirect_hook(hook: impl Fn(&str) + Send + Sync + 'static) {
@@ -11,3 +27,61 @@ pub fn call_redirect_hook(path: &str) {
         hook(path)
     }
 }
+
+/// Redirects the client to the given path once the current server function has returned.
+///
+/// This sets the `Location` header and the [`REDIRECT_HEADER`], so that the server function
+/// client calls the redirect hook with `path`. If the request was a plain HTML form
+/// submission, the response also has a `302 Found` status so that the browser navigates to it.
+///
+/// This does nothing if it is called outside a server function.
+pub fn redirect(path: &str) {
+    if let Some(options) = use_context::<ResponseOptions>() {
+        options.insert_header("Location", path);
+        options.insert_header(REDIRECT_HEADER, "true");
+    }
+}
+
+/// Sets a `302 Found` status if [`redirect`] has been called while handling a request that
+/// the browser (rather than the server function client) will follow itself.
+pub(crate) fn set_redirect_status(options: &ResponseOptions, accepts_html: bool) {
+    if accepts_html && options.status().is_none() && options.header(REDIRECT_HEADER).is_some() {
+        options.set_status(302);
+    }
+}
+
+/// Redirects a plain HTML form submission back to the page it came from, unless the server
+/// function has already called [`redirect`].
+///
+/// This means that server functions can be called from a `<form>` before (or without)
+/// hydration, without the browser showing the encoded result to the user.
+pub(crate) fn redirect_to_referer(
+    options: &ResponseOptions,
+    referer: &str,
+    path: &str,
+    error: Option<String>,
+) {
+    if options.header("Location").is_some() {
+        return;
+    }
+
+    let (page, query) = referer.split_once('?').unwrap_or((referer, ""));
+    let mut params = form_urlencoded::Serializer::new(String::new());
+    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+        if key != PATH_QUERY_PARAM && key != ERROR_QUERY_PARAM {
+            params.append_pair(&key, &value);
+        }
+    }
+    if let Some(error) = error {
+        params.append_pair(PATH_QUERY_PARAM, path);
+        params.append_pair(ERROR_QUERY_PARAM, &error);
+    }
+    let query = params.finish();
+
+    options.set_status(302);
+    if query.is_empty() {
+        options.insert_header("Location", page);
+    } else {
+        options.insert_header("Location", format!("{page}?{query}"));
+    }
+}
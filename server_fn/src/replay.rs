@@ -0,0 +1,510 @@
+//! Recording server function calls, and replaying them later without a server.
+//!
+//! [`ReplayClient`] wraps another client, like `BrowserClient` or `ReqwestClient`. Normally
+//! it just sends each call with that client. Calls made in a future that is run with a
+//! [`Replay`] are recorded or replayed instead: [`Replay::recording`] keeps each call that
+//! is sent, along with the response, and [`Replay::recorded`] returns them as [`Fixtures`]
+//! that can be saved to a file. [`Replay::replaying`] answers each call with the recorded
+//! response for the same path and arguments instead of sending it, so the front end can run
+//! without a backend and tests always see the same responses.
+//!
+//! ```ignore
+//! use server_fns::{client::browser::BrowserClient, replay::{Fixtures, Replay, ReplayClient}};
+//!
+//! #[server(client = ReplayClient<BrowserClient>)]
+//! pub async fn get_user(id: u32) -> Result<User, ServerFnError> {
+//!     todo!()
+//! }
+//!
+//! // while the backend is running
+//! let recording = Replay::recording();
+//! recording.run(get_user(1)).await?;
+//! let json = recording.recorded().to_json()?;
+//!
+//! // later, without the backend
+//! let replay = Replay::replaying(Fixtures::from_json(include_str!("fixtures.json"))?);
+//! replay.run(get_user(1)).await?; // the same user as before
+//! ```
+//!
+//! Each [`Replay`] only applies to the futures that it runs, so tests that run in parallel
+//! can each record or replay their own calls.
+//!
+//! Calls are matched on their method, path, query string and body, so a call with different
+//! arguments fails with [`ServerFnError::Request`] rather than getting another call's
+//! response. If the same call was recorded more than once, its responses are replayed in
+//! order, and the last one is repeated once they have all been used. Streaming responses are
+//! recorded in full, and WebSockets are always opened with the wrapped client.
+
+use crate::{
+    client::Client, codec::MessageStream, error::ServerFnError, request::ClientReq,
+    response::ClientRes,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::Bytes;
+use futures::Stream;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+/// Calls that have been recorded, to be saved and replayed later.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fixtures {
+    pub calls: Vec<RecordedCall>,
+}
+
+impl Fixtures {
+    /// Parses fixtures from JSON, as written by [`to_json`](Self::to_json).
+    pub fn from_json(json: &str) -> Result<Self, ServerFnError> {
+        serde_json::from_str(json).map_err(|e| ServerFnError::Deserialization(e.to_string()))
+    }
+
+    /// Writes the fixtures as pretty-printed JSON.
+    pub fn to_json(&self) -> Result<String, ServerFnError> {
+        serde_json::to_string_pretty(self).map_err(|e| ServerFnError::Serialization(e.to_string()))
+    }
+
+    /// Reads fixtures from a JSON file.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, ServerFnError> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| ServerFnError::Deserialization(e.to_string()))?;
+        Self::from_json(&json)
+    }
+
+    /// Writes the fixtures to a JSON file, replacing it if it exists.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> Result<(), ServerFnError> {
+        std::fs::write(path, self.to_json()?)
+            .map_err(|e| ServerFnError::Serialization(e.to_string()))
+    }
+}
+
+/// A call to a server function, and the response that the server sent.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedCall {
+    pub method: String,
+    pub path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    pub body: RecordedBody,
+    pub response: RecordedResponse,
+}
+
+/// A response to a [`RecordedCall`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub has_redirect: bool,
+    pub body: RecordedBody,
+}
+
+/// A request or response body, kept as text when it is valid UTF-8 so that fixtures can be
+/// read and edited by hand.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordedBody {
+    Text(String),
+    Base64(String),
+}
+
+impl RecordedBody {
+    fn new(body: &Bytes) -> Self {
+        match std::str::from_utf8(body) {
+            Ok(text) => Self::Text(text.to_string()),
+            Err(_) => Self::Base64(STANDARD.encode(body)),
+        }
+    }
+
+    pub(crate) fn to_bytes<CustErr>(&self) -> Result<Bytes, ServerFnError<CustErr>> {
+        match self {
+            Self::Text(text) => Ok(Bytes::from(text.clone())),
+            Self::Base64(data) => STANDARD
+                .decode(data)
+                .map(Bytes::from)
+                .map_err(|e| ServerFnError::Deserialization(e.to_string())),
+        }
+    }
+}
+
+enum Mode {
+    Recording(Vec<RecordedCall>),
+    /// Each call, and whether its response has been replayed yet.
+    Replaying(Vec<(RecordedCall, bool)>),
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Replay>> = const { RefCell::new(None) };
+}
+
+/// Records or replays the calls made with a [`ReplayClient`] in the futures that it runs.
+///
+/// Clones share the same recorded calls or fixtures.
+#[derive(Clone)]
+pub struct Replay(Arc<Mutex<Mode>>);
+
+impl Replay {
+    /// Records the calls that are sent.
+    pub fn recording() -> Self {
+        Self(Arc::new(Mutex::new(Mode::Recording(Vec::new()))))
+    }
+
+    /// Answers every call from the given fixtures, instead of sending it.
+    pub fn replaying(fixtures: Fixtures) -> Self {
+        let calls = fixtures
+            .calls
+            .into_iter()
+            .map(|call| (call, false))
+            .collect();
+        Self(Arc::new(Mutex::new(Mode::Replaying(calls))))
+    }
+
+    /// Returns the calls that have been recorded so far, or none if this is replaying.
+    pub fn recorded(&self) -> Fixtures {
+        match &*self.0.lock().expect("replay lock poisoned") {
+            Mode::Recording(calls) => Fixtures {
+                calls: calls.clone(),
+            },
+            Mode::Replaying(_) => Fixtures::default(),
+        }
+    }
+
+    /// Records or replays every call made with a [`ReplayClient`] while `fut` runs.
+    pub fn run<F: Future>(&self, fut: F) -> WithReplay<F> {
+        WithReplay {
+            replay: self.clone(),
+            inner: Box::pin(fut),
+        }
+    }
+}
+
+/// A future that records or replays its calls, returned by [`Replay::run`].
+pub struct WithReplay<F> {
+    replay: Replay,
+    inner: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for WithReplay<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        struct Restore(Option<Replay>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                CURRENT.with(|current| *current.borrow_mut() = self.0.take());
+            }
+        }
+
+        let this = &mut *self;
+        let _restore = Restore(CURRENT.with(|current| current.replace(Some(this.replay.clone()))));
+        this.inner.as_mut().poll(cx)
+    }
+}
+
+/// A request made with [`ReplayClient`], which may be sent, recorded or replayed.
+#[derive(Debug, Clone)]
+pub struct ReplayRequest {
+    method: &'static str,
+    path: String,
+    query: Option<String>,
+    headers: Vec<(String, String)>,
+    body: Bytes,
+}
+
+impl ReplayRequest {
+    fn new(
+        method: &'static str,
+        path: &str,
+        accepts: &str,
+        content_type: &str,
+        query: Option<&str>,
+        body: Bytes,
+    ) -> Self {
+        Self {
+            method,
+            path: path.to_string(),
+            query: query.map(String::from),
+            headers: vec![
+                ("content-type".to_string(), content_type.to_string()),
+                ("accept".to_string(), accepts.to_string()),
+            ],
+            body,
+        }
+    }
+
+    fn header(&self, name: &str) -> &str {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+            .unwrap_or_default()
+    }
+
+    /// Whether this is the same call as `call`, with the same arguments.
+    fn matches(&self, call: &RecordedCall) -> bool {
+        self.method == call.method
+            && self.path == call.path
+            && self.query == call.query
+            && call
+                .body
+                .to_bytes::<()>()
+                .is_ok_and(|body| body == self.body)
+    }
+
+    fn record(&self, response: RecordedResponse) -> RecordedCall {
+        RecordedCall {
+            method: self.method.to_string(),
+            path: self.path.clone(),
+            query: self.query.clone(),
+            body: RecordedBody::new(&self.body),
+            response,
+        }
+    }
+
+    /// Converts this into a request for the underlying client.
+    fn into_client_req<R: ClientReq<CustErr>, CustErr>(self) -> Result<R, ServerFnError<CustErr>> {
+        let accepts = self.header("accept").to_string();
+        let content_type = self.header("content-type").to_string();
+        let mut req = match self.method {
+            "GET" => R::try_new_get(
+                &self.path,
+                &accepts,
+                &content_type,
+                self.query.as_deref().unwrap_or_default(),
+            )?,
+            _ => R::try_new_post_bytes(&self.path, &accepts, &content_type, self.body)?,
+        };
+        for (name, value) in &self.headers {
+            if !name.eq_ignore_ascii_case("accept") && !name.eq_ignore_ascii_case("content-type") {
+                req = req.try_add_header(name, value)?;
+            }
+        }
+        Ok(req)
+    }
+}
+
+impl<CustErr> ClientReq<CustErr> for ReplayRequest {
+    type FormData = ();
+
+    fn try_new_get(
+        path: &str,
+        accepts: &str,
+        content_type: &str,
+        query: &str,
+    ) -> Result<Self, ServerFnError<CustErr>> {
+        Ok(Self::new(
+            "GET",
+            path,
+            accepts,
+            content_type,
+            Some(query),
+            Bytes::new(),
+        ))
+    }
+
+    fn try_new_post(
+        path: &str,
+        accepts: &str,
+        content_type: &str,
+        body: String,
+    ) -> Result<Self, ServerFnError<CustErr>> {
+        Ok(Self::new(
+            "POST",
+            path,
+            accepts,
+            content_type,
+            None,
+            Bytes::from(body),
+        ))
+    }
+
+    fn try_new_post_bytes(
+        path: &str,
+        accepts: &str,
+        content_type: &str,
+        body: Bytes,
+    ) -> Result<Self, ServerFnError<CustErr>> {
+        Ok(Self::new("POST", path, accepts, content_type, None, body))
+    }
+
+    fn try_new_multipart(
+        _path: &str,
+        _accepts: &str,
+        _body: Self::FormData,
+    ) -> Result<Self, ServerFnError<CustErr>> {
+        Err(ServerFnError::Request(
+            "multipart forms can't be recorded".into(),
+        ))
+    }
+
+    fn try_add_header(mut self, name: &str, value: &str) -> Result<Self, ServerFnError<CustErr>> {
+        self.headers
+            .retain(|(header, _)| !header.eq_ignore_ascii_case(name));
+        self.headers.push((name.to_string(), value.to_string()));
+        Ok(self)
+    }
+}
+
+/// The response to a call made with [`ReplayClient`].
+#[derive(Debug, Clone)]
+pub struct ReplayResponse {
+    status: u16,
+    status_text: String,
+    location: String,
+    has_redirect: bool,
+    body: Bytes,
+}
+
+impl ReplayResponse {
+    async fn from_client_res<R: ClientRes<CustErr>, CustErr>(
+        res: R,
+    ) -> Result<Self, ServerFnError<CustErr>> {
+        Ok(Self {
+            status: res.status(),
+            status_text: res.status_text(),
+            location: res.location(),
+            has_redirect: res.has_redirect(),
+            body: res.try_into_bytes().await?,
+        })
+    }
+
+    fn from_recorded<CustErr>(response: &RecordedResponse) -> Result<Self, ServerFnError<CustErr>> {
+        Ok(Self {
+            status: response.status,
+            status_text: response.status.to_string(),
+            location: response.location.clone().unwrap_or_default(),
+            has_redirect: response.has_redirect,
+            body: response.body.to_bytes()?,
+        })
+    }
+
+    fn to_recorded(&self) -> RecordedResponse {
+        RecordedResponse {
+            status: self.status,
+            // clients fall back to the URL of the request, which is only needed for redirects
+            location: ((300..=399).contains(&self.status) || self.has_redirect)
+                .then(|| self.location.clone()),
+            has_redirect: self.has_redirect,
+            body: RecordedBody::new(&self.body),
+        }
+    }
+}
+
+impl<CustErr> ClientRes<CustErr> for ReplayResponse {
+    async fn try_into_string(self) -> Result<String, ServerFnError<CustErr>> {
+        String::from_utf8(self.body.to_vec())
+            .map_err(|e| ServerFnError::Deserialization(e.to_string()))
+    }
+
+    async fn try_into_bytes(self) -> Result<Bytes, ServerFnError<CustErr>> {
+        Ok(self.body)
+    }
+
+    fn try_into_stream(
+        self,
+    ) -> Result<
+        impl Stream<Item = Result<Bytes, ServerFnError>> + Send + 'static,
+        ServerFnError<CustErr>,
+    > {
+        Ok(futures::stream::once(async move { Ok(self.body) }))
+    }
+
+    fn status(&self) -> u16 {
+        self.status
+    }
+
+    fn status_text(&self) -> String {
+        self.status_text.clone()
+    }
+
+    fn location(&self) -> String {
+        self.location.clone()
+    }
+
+    fn has_redirect(&self) -> bool {
+        self.has_redirect
+    }
+}
+
+/// Returns the recorded response to `req`, if calls are being replayed.
+fn replayed<CustErr>(
+    replay: &Replay,
+    req: &ReplayRequest,
+) -> Option<Result<ReplayResponse, ServerFnError<CustErr>>> {
+    let mut mode = replay.0.lock().expect("replay lock poisoned");
+    let Mode::Replaying(calls) = &mut *mode else {
+        return None;
+    };
+    let mut matching = calls
+        .iter_mut()
+        .filter(|(call, _)| req.matches(call))
+        .peekable();
+    let mut last = None;
+    while let Some((call, replayed)) = matching.next() {
+        if !*replayed || matching.peek().is_none() {
+            *replayed = true;
+            last = Some(call);
+            break;
+        }
+    }
+    Some(match last {
+        Some(call) => ReplayResponse::from_recorded(&call.response),
+        None => Err(ServerFnError::Request(format!(
+            "no recorded call to {} matches these arguments",
+            req.path
+        ))),
+    })
+}
+
+/// A [`Client`] that sends calls with the client `C`, and can record them or replay them
+/// instead.
+///
+/// See the [module documentation](self) for details.
+pub struct ReplayClient<C>(PhantomData<C>);
+
+impl<CustErr, C> Client<CustErr> for ReplayClient<C>
+where
+    C: Client<CustErr>,
+    CustErr: Send,
+{
+    type Request = ReplayRequest;
+    type Response = ReplayResponse;
+
+    async fn send(req: Self::Request) -> Result<Self::Response, ServerFnError<CustErr>> {
+        let Some(replay) = CURRENT.with(|current| current.borrow().clone()) else {
+            let res = C::send(req.into_client_req()?).await?;
+            return ReplayResponse::from_client_res(res).await;
+        };
+        if let Some(res) = replayed(&replay, &req) {
+            return res;
+        }
+
+        let recorded = req.clone();
+        let res = C::send(req.into_client_req()?).await?;
+        let res = ReplayResponse::from_client_res(res).await?;
+        if let Mode::Recording(calls) = &mut *replay.0.lock().expect("replay lock poisoned") {
+            calls.push(recorded.record(res.to_recorded()));
+        }
+        Ok(res)
+    }
+
+    // WebSockets can't be recorded
+    fn open_websocket(
+        path: &str,
+        input: MessageStream,
+    ) -> impl Future<Output = Result<MessageStream, ServerFnError<CustErr>>> + Send
+    where
+        CustErr: DeserializeOwned,
+    {
+        C::open_websocket(path, input)
+    }
+}
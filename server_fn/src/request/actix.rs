@@ -1,17 +1,81 @@
-use crate::{error::ServerFnError, request::Req};
-use actix_web::{FromRequest, HttpRequest};
+use crate::{
+    codec::MessageStream,
+    context::ServerContext,
+    error::ServerFnError,
+    request::{BodyLimit, Req},
+};
+use actix_web::{dev::Payload, HttpMessage, HttpRequest};
 use bytes::Bytes;
-use futures::Stream;
+use futures::{channel::oneshot, Stream, StreamExt, TryStreamExt};
 use send_wrapper::SendWrapper;
-use std::future::Future;
+use std::{future::Future, net::SocketAddr};
 
+#[derive(Clone)]
 pub struct ActixRequest(pub(crate) SendWrapper<HttpRequest>);
 
+/// The body of the request, kept in its extensions until it is read, because the request
+/// itself has to be cloned into the context.
+struct RequestPayload(Payload);
+
+impl From<HttpRequest> for ActixRequest {
+    fn from(value: HttpRequest) -> Self {
+        Self(SendWrapper::new(value))
+    }
+}
+
+impl From<(HttpRequest, Payload)> for ActixRequest {
+    fn from((req, payload): (HttpRequest, Payload)) -> Self {
+        req.extensions_mut().insert(RequestPayload(payload));
+        Self(SendWrapper::new(req))
+    }
+}
+
+impl ActixRequest {
+    /// Takes the body of the request, as a stream that fails as soon as more than its
+    /// [`BodyLimit`] has been read.
+    fn into_body<CustErr>(self) -> impl Stream<Item = Result<Bytes, ServerFnError<CustErr>>> {
+        let limit = self.0.extensions().get::<BodyLimit>().copied();
+        let payload = self
+            .0
+            .extensions_mut()
+            .remove::<RequestPayload>()
+            .map_or(Payload::None, |payload| payload.0);
+        let mut read = 0u64;
+        payload.map(move |chunk| {
+            let chunk = chunk.map_err(|e| ServerFnError::Deserialization(e.to_string()))?;
+            read += chunk.len() as u64;
+            match limit {
+                Some(BodyLimit(limit)) if read > limit => Err(ServerFnError::PayloadTooLarge(limit)),
+                _ => Ok(chunk),
+            }
+        })
+    }
+}
+
 impl<CustErr> Req<CustErr> for ActixRequest {
+    fn method(&self) -> &str {
+        self.0.method().as_str()
+    }
+
+    fn path(&self) -> &str {
+        self.0.path()
+    }
+
     fn as_query(&self) -> Option<&str> {
         self.0.uri().query()
     }
 
+    fn header(&self, name: &str) -> Option<String> {
+        self.0
+            .headers()
+            .get(name)
+            .map(|h| String::from_utf8_lossy(h.as_bytes()).to_string())
+    }
+
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        self.0.peer_addr()
+    }
+
     fn to_content_type(&self) -> Option<String> {
         self.0
             .headers()
@@ -19,13 +83,35 @@ impl<CustErr> Req<CustErr> for ActixRequest {
             .map(|h| String::from_utf8_lossy(h.as_bytes()).to_string())
     }
 
+    fn accepts(&self) -> Option<String> {
+        self.0
+            .headers()
+            .get("Accept")
+            .map(|h| String::from_utf8_lossy(h.as_bytes()).to_string())
+    }
+
+    fn referer(&self) -> Option<String> {
+        self.0
+            .headers()
+            .get("Referer")
+            .map(|h| String::from_utf8_lossy(h.as_bytes()).to_string())
+    }
+
+    fn with_body_limit(self, limit: u64) -> Self {
+        self.0.extensions_mut().insert(BodyLimit(limit));
+        self
+    }
+
+    fn provide_context(&self, context: &ServerContext) {
+        context.insert(self.clone());
+    }
+
     fn try_into_bytes(self) -> impl Future<Output = Result<Bytes, ServerFnError<CustErr>>> + Send {
         // Actix is going to keep this on a single thread anyway so it's fine to wrap it
         // with SendWrapper, which makes it `Send` but will panic if it moves to another thread
         SendWrapper::new(async move {
-            Bytes::extract(&self.0)
-                .await
-                .map_err(|e| ServerFnError::Deserialization(e.to_string()))
+            let chunks: Vec<Bytes> = self.into_body().try_collect().await?;
+            Ok(chunks.concat().into())
         })
     }
 
@@ -35,8 +121,8 @@ impl<CustErr> Req<CustErr> for ActixRequest {
         // Actix is going to keep this on a single thread anyway so it's fine to wrap it
         // with SendWrapper, which makes it `Send` but will panic if it moves to another thread
         SendWrapper::new(async move {
-            String::extract(&self.0)
-                .await
+            let bytes = self.try_into_bytes().await?;
+            String::from_utf8(bytes.to_vec())
                 .map_err(|e| ServerFnError::Deserialization(e.to_string()))
         })
     }
@@ -45,6 +131,15 @@ impl<CustErr> Req<CustErr> for ActixRequest {
         self,
     ) -> Result<impl Stream<Item = Result<Bytes, ServerFnError>> + Send, ServerFnError<CustErr>>
     {
-        Ok(futures::stream::once(async { todo!() }))
+        // the stream is read by the server function, on the same thread as the request
+        Ok(SendWrapper::new(self.into_body()))
+    }
+
+    async fn try_into_websocket(
+        self,
+    ) -> Result<(MessageStream, oneshot::Sender<MessageStream>), ServerFnError<CustErr>> {
+        Err(ServerFnError::Request(
+            "WebSocket server functions aren't supported by the actix integration yet".into(),
+        ))
     }
 }
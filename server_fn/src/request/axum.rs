@@ -1,27 +1,100 @@
-use crate::{error::ServerFnError, request::Req};
-use axum::body::{Body, Bytes};
-use futures::{Stream, StreamExt};
-use http::{header::CONTENT_TYPE, Request};
-use http_body_util::BodyExt;
+use crate::{
+    codec::{
+        close_error, close_reason, received_messages, MessageStream, Received, WebsocketMessage,
+        ERROR_CLOSE_CODE,
+    },
+    context::{use_context, ServerContext},
+    error::ServerFnError,
+    request::{BodyLimit, ClientReq, Req},
+    response::ResponseOptions,
+};
+use axum::{
+    body::{Body, Bytes},
+    extract::{
+        ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, FromRequestParts,
+    },
+};
+use futures::{
+    channel::{mpsc, oneshot},
+    SinkExt, Stream, StreamExt,
+};
+use http::{
+    header::{HeaderName, HeaderValue, ACCEPT, CONTENT_LENGTH, CONTENT_TYPE, REFERER},
+    Method, Request,
+};
+use http_body_util::{BodyExt, LengthLimitError, Limited};
+use std::{error::Error, net::SocketAddr, pin::pin};
 
 impl<CustErr> Req<CustErr> for Request<Body> {
+    fn method(&self) -> &str {
+        self.method().as_str()
+    }
+
+    fn path(&self) -> &str {
+        self.uri().path()
+    }
+
     fn as_query(&self) -> Option<&str> {
         self.uri().query()
     }
 
+    fn header(&self, name: &str) -> Option<String> {
+        self.headers()
+            .get(name)
+            .map(|h| String::from_utf8_lossy(h.as_bytes()).to_string())
+    }
+
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        // only present if the app is served with `into_make_service_with_connect_info`
+        self.extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|info| info.0)
+    }
+
     fn to_content_type(&self) -> Option<String> {
         self.headers()
             .get(CONTENT_TYPE)
             .map(|h| String::from_utf8_lossy(h.as_bytes()).to_string())
     }
 
+    fn accepts(&self) -> Option<String> {
+        self.headers()
+            .get(ACCEPT)
+            .map(|h| String::from_utf8_lossy(h.as_bytes()).to_string())
+    }
+
+    fn referer(&self) -> Option<String> {
+        self.headers()
+            .get(REFERER)
+            .map(|h| String::from_utf8_lossy(h.as_bytes()).to_string())
+    }
+
+    fn with_body_limit(self, limit: u64) -> Self {
+        let (mut parts, body) = self.into_parts();
+        parts.extensions.insert(BodyLimit(limit));
+        let limit = usize::try_from(limit).unwrap_or(usize::MAX);
+        Request::from_parts(parts, Body::new(Limited::new(body, limit)))
+    }
+
+    fn provide_context(&self, context: &ServerContext) {
+        let (mut parts, _) = Request::new(()).into_parts();
+        parts.method = self.method().clone();
+        parts.uri = self.uri().clone();
+        parts.version = self.version();
+        parts.headers = self.headers().clone();
+        parts.extensions = self.extensions().clone();
+        context.insert(parts);
+    }
+
     async fn try_into_bytes(self) -> Result<Bytes, ServerFnError<CustErr>> {
-        let (_parts, body) = self.into_parts();
+        let (parts, body) = self.into_parts();
+        let limit = parts.extensions.get::<BodyLimit>().copied();
 
         body.collect()
             .await
             .map(|c| c.to_bytes())
-            .map_err(|e| ServerFnError::Deserialization(e.to_string()))
+            .map_err(|e| body_error(e, limit))
     }
 
     async fn try_into_string(self) -> Result<String, ServerFnError<CustErr>> {
@@ -35,9 +108,170 @@ impl<CustErr> Req<CustErr> for Request<Body> {
         self,
     ) -> Result<impl Stream<Item = Result<Bytes, ServerFnError>> + Send, ServerFnError<CustErr>>
     {
+        let limit = self.extensions().get::<BodyLimit>().copied();
         Ok(self
             .into_body()
             .into_data_stream()
-            .map(|chunk| chunk.map_err(|e| ServerFnError::Deserialization(e.to_string()))))
+            .map(move |chunk| chunk.map_err(|e| body_error(e, limit))))
+    }
+
+    async fn try_into_websocket(
+        self,
+    ) -> Result<(MessageStream, oneshot::Sender<MessageStream>), ServerFnError<CustErr>> {
+        let (mut parts, _) = self.into_parts();
+        let upgrade = WebSocketUpgrade::from_request_parts(&mut parts, &())
+            .await
+            .map_err(|e| ServerFnError::Request(e.body_text()))?;
+        let (input_tx, input_rx) = mpsc::channel(16);
+        let (output_tx, output_rx) = oneshot::channel();
+        let res = upgrade.on_upgrade(move |socket| serve_websocket(socket, input_tx, output_rx));
+
+        // the upgrade completes when any response with these parts is sent
+        let options = use_context::<ResponseOptions>().ok_or_else(|| {
+            ServerFnError::ServerError("a WebSocket needs ResponseOptions in the context".into())
+        })?;
+        options.set_status(res.status().as_u16());
+        for (name, value) in res.headers() {
+            if let Ok(value) = value.to_str() {
+                options.insert_header(name.as_str(), value);
+            }
+        }
+        Ok((received_messages(input_rx), output_tx))
+    }
+}
+
+/// Passes messages between the WebSocket and the server function, until either the client
+/// closes the connection or the server function's output ends.
+async fn serve_websocket(
+    socket: WebSocket,
+    mut input: mpsc::Sender<Received>,
+    output: oneshot::Receiver<MessageStream>,
+) {
+    let (mut sink, mut stream) = socket.split();
+    let receive = async move {
+        while let Some(msg) = stream.next().await {
+            let received = match msg {
+                Ok(Message::Binary(data)) => Received::Message(WebsocketMessage::Binary(data.into())),
+                Ok(Message::Text(text)) => Received::Message(WebsocketMessage::Text(text)),
+                Ok(Message::Close(frame)) => {
+                    Received::Closed(frame.and_then(|frame| close_error(frame.code, &frame.reason)))
+                }
+                // pings are answered by axum
+                Ok(Message::Ping(_) | Message::Pong(_)) => continue,
+                Err(e) => Received::Closed(Some(ServerFnError::Request(e.to_string()))),
+            };
+            if input.send(received).await.is_err() {
+                break;
+            }
+        }
+    };
+    let send = async move {
+        let Ok(mut output) = output.await else {
+            return;
+        };
+        let close = loop {
+            let msg = match output.next().await {
+                Some(Ok(WebsocketMessage::Binary(data))) => Message::Binary(data.into()),
+                Some(Ok(WebsocketMessage::Text(text))) => Message::Text(text),
+                Some(Err(err)) => {
+                    break Some(CloseFrame {
+                        code: ERROR_CLOSE_CODE,
+                        reason: close_reason(&err).into(),
+                    })
+                }
+                None => break None,
+            };
+            if sink.send(msg).await.is_err() {
+                return;
+            }
+        };
+        _ = sink.send(Message::Close(close)).await;
+    };
+    futures::future::select(pin!(receive), pin!(send)).await;
+}
+
+/// Requests that are built on the server and run in the same process, by
+/// [`LocalClient`](crate::client::local::LocalClient).
+impl<CustErr> ClientReq<CustErr> for Request<Body> {
+    type FormData = ();
+
+    fn try_new_get(
+        path: &str,
+        accepts: &str,
+        content_type: &str,
+        query: &str,
+    ) -> Result<Self, ServerFnError<CustErr>> {
+        local_request(
+            Method::GET,
+            &format!("{path}?{query}"),
+            accepts,
+            content_type,
+            Bytes::new(),
+        )
+    }
+
+    fn try_new_post(
+        path: &str,
+        accepts: &str,
+        content_type: &str,
+        body: String,
+    ) -> Result<Self, ServerFnError<CustErr>> {
+        local_request(Method::POST, path, accepts, content_type, Bytes::from(body))
+    }
+
+    fn try_new_post_bytes(
+        path: &str,
+        accepts: &str,
+        content_type: &str,
+        body: Bytes,
+    ) -> Result<Self, ServerFnError<CustErr>> {
+        local_request(Method::POST, path, accepts, content_type, body)
+    }
+
+    fn try_new_multipart(
+        _path: &str,
+        _accepts: &str,
+        _body: Self::FormData,
+    ) -> Result<Self, ServerFnError<CustErr>> {
+        Err(ServerFnError::Request(
+            "multipart forms can only be sent from the browser".into(),
+        ))
+    }
+
+    fn try_add_header(mut self, name: &str, value: &str) -> Result<Self, ServerFnError<CustErr>> {
+        let name = HeaderName::try_from(name).map_err(|e| ServerFnError::Request(e.to_string()))?;
+        let value =
+            HeaderValue::try_from(value).map_err(|e| ServerFnError::Request(e.to_string()))?;
+        self.headers_mut().insert(name, value);
+        Ok(self)
+    }
+}
+
+fn local_request<CustErr>(
+    method: Method,
+    uri: &str,
+    accepts: &str,
+    content_type: &str,
+    body: Bytes,
+) -> Result<Request<Body>, ServerFnError<CustErr>> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(CONTENT_TYPE, content_type)
+        .header(ACCEPT, accepts)
+        .header(CONTENT_LENGTH, body.len())
+        .body(Body::from(body))
+        .map_err(|e| ServerFnError::Request(e.to_string()))
+}
+
+/// Converts an error reading the body, which may be because it was longer than its limit.
+fn body_error<CustErr>(err: axum::Error, limit: Option<BodyLimit>) -> ServerFnError<CustErr> {
+    let mut source: Option<&(dyn Error + 'static)> = Some(&err);
+    while let Some(err) = source {
+        if let (true, Some(BodyLimit(limit))) = (err.is::<LengthLimitError>(), limit) {
+            return ServerFnError::PayloadTooLarge(limit);
+        }
+        source = err.source();
     }
+    ServerFnError::Deserialization(err.to_string())
 }
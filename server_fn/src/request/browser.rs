@@ -90,4 +90,9 @@ impl<CustErr> ClientReq<CustErr> for BrowserRequest {
                 .map_err(|e| ServerFnError::Request(e.to_string()))?,
         )))
     }
+
+    fn try_add_header(self, name: &str, value: &str) -> Result<Self, ServerFnError<CustErr>> {
+        self.0.headers().set(name, value);
+        Ok(self)
+    }
 }
@@ -1,7 +1,7 @@
-use crate::error::ServerFnError;
+use crate::{codec::MessageStream, context::ServerContext, error::ServerFnError};
 use bytes::Bytes;
-use futures::Stream;
-use std::future::Future;
+use futures::{channel::oneshot, Stream};
+use std::{future::Future, net::SocketAddr};
 
 #[cfg(feature = "actix")]
 pub mod actix;
@@ -45,6 +45,9 @@ where
         accepts: &str,
         body: Self::FormData,
     ) -> Result<Self, ServerFnError<CustErr>>;
+
+    /// Adds a header to the request, replacing any existing header with the same name.
+    fn try_add_header(self, name: &str, value: &str) -> Result<Self, ServerFnError<CustErr>>;
 }
 
 /// Represents the request as received by the server.
@@ -52,12 +55,38 @@ pub trait Req<CustErr>
 where
     Self: Sized,
 {
+    /// Returns the HTTP method of the request.
+    fn method(&self) -> &str;
+
+    /// Returns the path of the request’s URL.
+    fn path(&self) -> &str;
+
     /// Returns the query string of the request’s URL, starting after the `?`.
     fn as_query(&self) -> Option<&str>;
 
+    /// Returns the value of the given header, if any.
+    fn header(&self, name: &str) -> Option<String>;
+
+    /// Returns the address of the client that made the request, if known.
+    fn remote_addr(&self) -> Option<SocketAddr>;
+
     /// Returns the `Content-Type` header, if any.
     fn to_content_type(&self) -> Option<String>;
 
+    /// Returns the `Accept` header, if any.
+    fn accepts(&self) -> Option<String>;
+
+    /// Returns the `Referer` header, if any.
+    fn referer(&self) -> Option<String>;
+
+    /// Limits the body to `limit` bytes, so that reading any more of it fails with
+    /// [`ServerFnError::PayloadTooLarge`].
+    fn with_body_limit(self, limit: u64) -> Self;
+
+    /// Adds the framework-specific parts of the request (its method, URI, headers, and so on)
+    /// to the context in which the server function will run.
+    fn provide_context(&self, context: &ServerContext);
+
     /// Attempts to extract the body of the request into [`Bytes`].
     fn try_into_bytes(self) -> impl Future<Output = Result<Bytes, ServerFnError<CustErr>>> + Send;
 
@@ -69,6 +98,37 @@ where
     fn try_into_stream(
         self,
     ) -> Result<impl Stream<Item = Result<Bytes, ServerFnError>> + Send, ServerFnError<CustErr>>;
+
+    /// Accepts a request to upgrade to a WebSocket, returning the messages that will be
+    /// received from the client and a sender for the messages to send to it.
+    ///
+    /// The status and headers that complete the upgrade are set in the current
+    /// [`ResponseOptions`](crate::response::ResponseOptions), and the connection opens once
+    /// the response has been sent.
+    fn try_into_websocket(
+        self,
+    ) -> impl Future<
+        Output = Result<(MessageStream, oneshot::Sender<MessageStream>), ServerFnError<CustErr>>,
+    > + Send;
+}
+
+/// The body limit that has been applied to a request, kept in its extensions.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BodyLimit(pub u64);
+
+/// Rejects the request if its `Content-Length` is larger than `limit`, and otherwise makes
+/// sure that no more than `limit` bytes of its body can be read.
+pub(crate) fn limit_body<R, CustErr>(req: R, limit: u64) -> Result<R, ServerFnError<CustErr>>
+where
+    R: Req<CustErr>,
+{
+    let length = req
+        .header("content-length")
+        .and_then(|length| length.parse::<u64>().ok());
+    if length.is_some_and(|length| length > limit) {
+        return Err(ServerFnError::PayloadTooLarge(limit));
+    }
+    Ok(req.with_body_limit(limit))
 }
 
 /// A mocked request type that can be used in place of the actual server request,
@@ -76,14 +136,46 @@ where
 pub struct BrowserMockReq;
 
 impl<CustErr> Req<CustErr> for BrowserMockReq {
+    fn method(&self) -> &str {
+        unreachable!()
+    }
+
+    fn path(&self) -> &str {
+        unreachable!()
+    }
+
     fn as_query(&self) -> Option<&str> {
         unreachable!()
     }
 
+    fn header(&self, _name: &str) -> Option<String> {
+        unreachable!()
+    }
+
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        unreachable!()
+    }
+
+    fn referer(&self) -> Option<String> {
+        unreachable!()
+    }
+
     fn to_content_type(&self) -> Option<String> {
         unreachable!()
     }
 
+    fn accepts(&self) -> Option<String> {
+        unreachable!()
+    }
+
+    fn with_body_limit(self, _limit: u64) -> Self {
+        unreachable!()
+    }
+
+    fn provide_context(&self, _context: &ServerContext) {
+        unreachable!()
+    }
+
     fn try_into_bytes(self) -> impl Future<Output = Result<Bytes, ServerFnError<CustErr>>> + Send {
         async { unreachable!() }
     }
@@ -100,4 +192,10 @@ impl<CustErr> Req<CustErr> for BrowserMockReq {
     {
         Ok(futures::stream::once(async { unreachable!() }))
     }
+
+    async fn try_into_websocket(
+        self,
+    ) -> Result<(MessageStream, oneshot::Sender<MessageStream>), ServerFnError<CustErr>> {
+        unreachable!()
+    }
 }
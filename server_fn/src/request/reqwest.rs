@@ -5,7 +5,7 @@ use crate::error::ServerFnError;
 use super::ClientReq;
 use bytes::Bytes;
 use once_cell::sync::Lazy;
-use reqwest::header::{ACCEPT, CONTENT_TYPE};
+use reqwest::header::{HeaderName, HeaderValue, ACCEPT, CONTENT_TYPE};
 pub use reqwest::{multipart::Form, Client, Method, Request, Url};
 
 pub(crate) static CLIENT: Lazy<Client> = Lazy::new(Client::new);
@@ -18,7 +18,7 @@ pub fn set_server_url(url: &'static str) {
     ROOT_URL.set(url).unwrap();
 }
 
-fn get_server_url() -> &'static str {
+pub(crate) fn get_server_url() -> &'static str {
     ROOT_URL
         .get()
         .expect("Call `set_root_url` before calling a server function.")
@@ -90,4 +90,12 @@ impl<CustErr> ClientReq<CustErr> for Request {
             .build()
             .map_err(|e| ServerFnError::Request(e.to_string()))?)
     }
+
+    fn try_add_header(mut self, name: &str, value: &str) -> Result<Self, ServerFnError<CustErr>> {
+        let name = HeaderName::try_from(name).map_err(|e| ServerFnError::Request(e.to_string()))?;
+        let value =
+            HeaderValue::try_from(value).map_err(|e| ServerFnError::Request(e.to_string()))?;
+        self.headers_mut().insert(name, value);
+        Ok(self)
+    }
 }
@@ -1,9 +1,15 @@
-use super::Res;
+use super::{Res, ResponseOptions};
 use crate::error::ServerFnError;
-use actix_web::{http::header, http::StatusCode, HttpResponse};
+use actix_web::{
+    body::{BodySize, MessageBody},
+    http::header::{self, HeaderName, HeaderValue},
+    http::StatusCode,
+    HttpResponse,
+};
 use bytes::Bytes;
 use futures::Stream;
 use send_wrapper::SendWrapper;
+use serde::Serialize;
 use std::fmt::Display;
 
 pub struct ActixResponse(pub(crate) SendWrapper<HttpResponse>);
@@ -16,7 +22,7 @@ impl ActixResponse {
 
 impl<CustErr> Res<CustErr> for ActixResponse
 where
-    CustErr: Display,
+    CustErr: Display + Serialize,
 {
     fn try_from_string(content_type: &str, data: String) -> Result<Self, ServerFnError<CustErr>> {
         let mut builder = HttpResponse::build(StatusCode::OK);
@@ -37,9 +43,20 @@ where
     }
 
     fn error_response(err: ServerFnError<CustErr>) -> Self {
-        ActixResponse(SendWrapper::new(
-            HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).body(err.to_string()),
-        ))
+        let status = StatusCode::from_u16(err.status_code())
+            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let mut builder = HttpResponse::build(status);
+        if let ServerFnError::RateLimited(secs) = &err {
+            builder.insert_header((header::RETRY_AFTER, *secs));
+        }
+        // serialized so that the client can deserialize it into the same error
+        let res = match serde_json::to_string(&err) {
+            Ok(json) => builder
+                .insert_header((header::CONTENT_TYPE, "application/json"))
+                .body(json),
+            Err(_) => builder.body(err.to_string()),
+        };
+        ActixResponse(SendWrapper::new(res))
     }
 
     fn try_from_stream(
@@ -48,4 +65,27 @@ where
     ) -> Result<Self, ServerFnError<CustErr>> {
         todo!()
     }
+
+    fn with_options(self, options: &ResponseOptions) -> Result<Self, ServerFnError<CustErr>> {
+        let mut res = self.into_inner();
+        let headers = res.headers_mut();
+        let status = options.apply(|name: HeaderName, value: HeaderValue, append| {
+            if append {
+                headers.append(name, value);
+            } else {
+                headers.insert(name, value);
+            }
+        })?;
+        if let Some(status) = status {
+            *res.status_mut() = status;
+        }
+        Ok(ActixResponse(SendWrapper::new(res)))
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        match self.0.body().size() {
+            BodySize::Sized(size) => Some(size),
+            _ => None,
+        }
+    }
 }
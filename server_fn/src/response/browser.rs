@@ -1,4 +1,4 @@
-use crate::error::ServerFnError;
+use crate::{error::ServerFnError, redirect::REDIRECT_HEADER};
 
 use super::ClientRes;
 use bytes::Bytes;
@@ -70,4 +70,8 @@ impl<CustErr> ClientRes<CustErr> for BrowserResponse {
             .get("Location")
             .unwrap_or_else(|| self.0.url())
     }
+
+    fn has_redirect(&self) -> bool {
+        self.0.headers().get(REDIRECT_HEADER).is_some()
+    }
 }
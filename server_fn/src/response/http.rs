@@ -1,14 +1,17 @@
-use super::Res;
+use super::{ClientRes, Res, ResponseOptions};
 use crate::error::{ServerFnError, ServerFnErrorErr};
-use axum::body::Body;
+use crate::redirect::REDIRECT_HEADER;
+use axum::body::{Body, HttpBody};
 use bytes::Bytes;
 use futures::{Stream, StreamExt};
-use http::Response;
+use http_body_util::BodyExt;
+use serde::Serialize;
+use http::{HeaderName, HeaderValue, Response, StatusCode};
 use std::fmt::{Debug, Display};
 
 impl<CustErr> Res<CustErr> for Response<Body>
 where
-    CustErr: Send + Sync + Debug + Display + 'static,
+    CustErr: Send + Sync + Debug + Display + Serialize + 'static,
 {
     fn try_from_string(content_type: &str, data: String) -> Result<Self, ServerFnError<CustErr>> {
         let builder = http::Response::builder();
@@ -42,9 +45,87 @@ where
     }
 
     fn error_response(err: ServerFnError<CustErr>) -> Self {
-        Response::builder()
-            .status(http::StatusCode::INTERNAL_SERVER_ERROR)
-            .body(Body::from(err.to_string()))
-            .unwrap()
+        let status = StatusCode::from_u16(err.status_code())
+            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let mut builder = Response::builder().status(status);
+        if let ServerFnError::RateLimited(secs) = &err {
+            builder = builder.header(http::header::RETRY_AFTER, *secs);
+        }
+        // serialized so that the client can deserialize it into the same error
+        match serde_json::to_string(&err) {
+            Ok(json) => builder
+                .header(http::header::CONTENT_TYPE, "application/json")
+                .body(Body::from(json))
+                .unwrap(),
+            Err(_) => builder.body(Body::from(err.to_string())).unwrap(),
+        }
+    }
+
+    fn with_options(mut self, options: &ResponseOptions) -> Result<Self, ServerFnError<CustErr>> {
+        let headers = self.headers_mut();
+        let status = options.apply(|name: HeaderName, value: HeaderValue, append| {
+            if append {
+                headers.append(name, value);
+            } else {
+                headers.insert(name, value);
+            }
+        })?;
+        if let Some(status) = status {
+            *self.status_mut() = status;
+        }
+        Ok(self)
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        self.body().size_hint().exact()
+    }
+}
+
+/// Responses to requests that were run in the same process, by
+/// [`LocalClient`](crate::client::local::LocalClient).
+impl<CustErr> ClientRes<CustErr> for Response<Body> {
+    async fn try_into_bytes(self) -> Result<Bytes, ServerFnError<CustErr>> {
+        self.into_body()
+            .collect()
+            .await
+            .map(|body| body.to_bytes())
+            .map_err(|e| ServerFnError::Deserialization(e.to_string()))
+    }
+
+    async fn try_into_string(self) -> Result<String, ServerFnError<CustErr>> {
+        let bytes = ClientRes::<CustErr>::try_into_bytes(self).await?;
+        String::from_utf8(bytes.to_vec()).map_err(|e| ServerFnError::Deserialization(e.to_string()))
+    }
+
+    fn try_into_stream(
+        self,
+    ) -> Result<
+        impl Stream<Item = Result<Bytes, ServerFnError>> + Send + 'static,
+        ServerFnError<CustErr>,
+    > {
+        Ok(self
+            .into_body()
+            .into_data_stream()
+            .map(|chunk| chunk.map_err(|e| ServerFnError::Response(e.to_string()))))
+    }
+
+    fn status(&self) -> u16 {
+        self.status().as_u16()
+    }
+
+    fn status_text(&self) -> String {
+        self.status().to_string()
+    }
+
+    fn location(&self) -> String {
+        // there is no URL to fall back to, as the request never left the server
+        self.headers()
+            .get("Location")
+            .map(|value| String::from_utf8_lossy(value.as_bytes()).to_string())
+            .unwrap_or_default()
+    }
+
+    fn has_redirect(&self) -> bool {
+        self.headers().get(REDIRECT_HEADER).is_some()
     }
 }
@@ -10,7 +10,10 @@ pub mod reqwest;
 use crate::error::ServerFnError;
 use bytes::Bytes;
 use futures::Stream;
-use std::future::Future;
+use std::{
+    future::Future,
+    sync::{Arc, RwLock},
+};
 
 /// Represents the response as created by the server;
 pub trait Res<CustErr>
@@ -30,6 +33,118 @@ where
     ) -> Result<Self, ServerFnError<CustErr>>;
 
     fn error_response(err: ServerFnError<CustErr>) -> Self;
+
+    /// Applies the status code and headers set by the server function to the response.
+    fn with_options(self, options: &ResponseOptions) -> Result<Self, ServerFnError<CustErr>>;
+
+    /// The size of the response body in bytes, if it is known before it is sent.
+    fn content_length(&self) -> Option<u64>;
+}
+
+/// Allows the body of a server function to set the status code and headers of its response.
+///
+/// A new `ResponseOptions` is added to the [`ServerContext`](crate::context::ServerContext)
+/// for each server function call, and can be accessed with
+/// [`use_context`](crate::context::use_context). Any changes are applied to the response,
+/// including error and streaming responses, after the server function has run.
+#[derive(Clone, Debug, Default)]
+pub struct ResponseOptions(Arc<RwLock<ResponseParts>>);
+
+/// The status code and headers that have been set with [`ResponseOptions`].
+#[derive(Clone, Debug, Default)]
+pub struct ResponseParts {
+    /// The status code for the response, if it should replace the default.
+    pub status: Option<u16>,
+    /// Headers that replace any existing header with the same name.
+    pub headers: Vec<(String, String)>,
+    /// Headers that are added alongside any existing header with the same name, like `Set-Cookie`.
+    pub appended_headers: Vec<(String, String)>,
+}
+
+impl ResponseOptions {
+    /// Sets the status code of the response.
+    pub fn set_status(&self, status: u16) {
+        self.0.write().expect("ResponseOptions lock poisoned").status = Some(status);
+    }
+
+    /// Sets a header, replacing any existing header with the same name.
+    pub fn insert_header(&self, name: impl Into<String>, value: impl Into<String>) {
+        let name = name.into();
+        let mut parts = self.0.write().expect("ResponseOptions lock poisoned");
+        parts
+            .headers
+            .retain(|(existing, _)| !existing.eq_ignore_ascii_case(&name));
+        parts.headers.push((name, value.into()));
+    }
+
+    /// Adds a header without replacing any existing header with the same name.
+    pub fn append_header(&self, name: impl Into<String>, value: impl Into<String>) {
+        self.0
+            .write()
+            .expect("ResponseOptions lock poisoned")
+            .appended_headers
+            .push((name.into(), value.into()));
+    }
+
+    /// Adds a `Set-Cookie` header with the given value.
+    pub fn set_cookie(&self, cookie: impl Into<String>) {
+        self.append_header("Set-Cookie", cookie);
+    }
+
+    /// Returns the value of a header that has been set with [`insert_header`](Self::insert_header).
+    pub fn header(&self, name: &str) -> Option<String> {
+        self.0
+            .read()
+            .expect("ResponseOptions lock poisoned")
+            .headers
+            .iter()
+            .find(|(existing, _)| existing.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.clone())
+    }
+
+    /// Returns the status code that has been set, if any.
+    pub fn status(&self) -> Option<u16> {
+        self.0.read().expect("ResponseOptions lock poisoned").status
+    }
+
+    /// Returns the status code and headers that have been set so far.
+    pub fn parts(&self) -> ResponseParts {
+        self.0.read().expect("ResponseOptions lock poisoned").clone()
+    }
+
+    /// Converts the headers that have been set into the header types of a framework and
+    /// passes each one to `add_header`, along with whether it should be appended rather than
+    /// replace an existing header. Returns the converted status code, if one has been set.
+    #[cfg(any(feature = "axum", feature = "actix"))]
+    pub(crate) fn apply<S, N, V, CustErr>(
+        &self,
+        mut add_header: impl FnMut(N, V, bool),
+    ) -> Result<Option<S>, ServerFnError<CustErr>>
+    where
+        S: TryFrom<u16>,
+        S::Error: std::fmt::Display,
+        N: TryFrom<String>,
+        N::Error: std::fmt::Display,
+        V: TryFrom<String>,
+        V::Error: std::fmt::Display,
+    {
+        let parts = self.parts();
+        let headers = parts.headers.into_iter().map(|(name, value)| (name, value, false));
+        let appended = parts
+            .appended_headers
+            .into_iter()
+            .map(|(name, value)| (name, value, true));
+        for (name, value, append) in headers.chain(appended) {
+            let name = N::try_from(name).map_err(|e| ServerFnError::Response(e.to_string()))?;
+            let value = V::try_from(value).map_err(|e| ServerFnError::Response(e.to_string()))?;
+            add_header(name, value, append);
+        }
+        parts
+            .status
+            .map(S::try_from)
+            .transpose()
+            .map_err(|e| ServerFnError::Response(e.to_string()))
+    }
 }
 
 /// Represents the response as received by the client.
@@ -57,6 +172,9 @@ pub trait ClientRes<CustErr> {
 
     /// The `Location` header or (if none is set), the URL of the response.
     fn location(&self) -> String;
+
+    /// Whether the response has the [`REDIRECT_HEADER`](crate::redirect::REDIRECT_HEADER) set.
+    fn has_redirect(&self) -> bool;
 }
 
 /// A mocked response type that can be used in place of the actual server response,
@@ -76,6 +194,14 @@ impl<CustErr> Res<CustErr> for BrowserMockRes {
         unreachable!()
     }
 
+    fn with_options(self, _options: &ResponseOptions) -> Result<Self, ServerFnError<CustErr>> {
+        unreachable!()
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        unreachable!()
+    }
+
     fn try_from_stream(
         content_type: &str,
         data: impl Stream<Item = Result<Bytes, ServerFnError<CustErr>>>,
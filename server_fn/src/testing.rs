@@ -0,0 +1,247 @@
+//! Calling server functions in tests, through the same routing, middleware and codecs as a
+//! request from a real client.
+//!
+//! A [`TestServer`] routes each of its server functions to
+//! [`handle_server_fn`](crate::axum::handle_server_fn). Calls are made with the arguments
+//! struct that the `#[server]` macro generates, and can add headers, cookies and request
+//! extensions before they are sent. The response can then be checked, and its output decoded
+//! just as the client would:
+//!
+//! ```ignore
+//! use server_fns::testing::TestServer;
+//!
+//! #[tokio::test]
+//! async fn adds_for_signed_in_users() {
+//!     let server = TestServer::new();
+//!     let res = server
+//!         .call(Add { a: 1, b: 2 })
+//!         .cookie("session", "abc")
+//!         .extension(Db::in_memory())
+//!         .send()
+//!         .await;
+//!     res.assert_status(200).assert_header("cache-control", "no-store");
+//!     assert_eq!(res.output().await.unwrap(), 3);
+//! }
+//! ```
+//!
+//! Global middleware, CORS and body limits are shared by every server, as they are set for
+//! the whole process.
+
+use crate::{
+    axum::{handle_server_fn, register_explicit, server_fns},
+    codec::{Encoding, FromRes, IntoReq},
+    error::ServerFnError,
+    read_error, ServerFn,
+};
+use axum::{body::Body, routing::any, Router};
+use http::{
+    header::{COOKIE, SET_COOKIE},
+    Extensions, HeaderMap, HeaderName, HeaderValue, Request, Response,
+};
+use serde::de::DeserializeOwned;
+use std::{collections::BTreeSet, fmt::Display, marker::PhantomData};
+use tower::ServiceExt;
+
+/// A router for calling server functions in tests, without binding a port.
+#[derive(Debug, Clone, Default)]
+pub struct TestServer {
+    paths: BTreeSet<&'static str>,
+}
+
+impl TestServer {
+    /// Creates a server with a route for every registered server function.
+    pub fn new() -> Self {
+        Self {
+            paths: server_fns()
+                .iter()
+                .map(|server_fn| server_fn.path())
+                .collect(),
+        }
+    }
+
+    /// Creates a server without any routes, to add server functions to with
+    /// [`register`](Self::register).
+    pub fn empty() -> Self {
+        Self::default()
+    }
+
+    /// Registers a server function with [`register_explicit`], for platforms that don't
+    /// support automatic registration, and adds a route for it.
+    pub fn register<T>(mut self) -> Self
+    where
+        T: ServerFn<ServerRequest = Request<Body>, ServerResponse = Response<Body>> + 'static,
+    {
+        register_explicit::<T>();
+        self.paths.insert(T::PATH);
+        self
+    }
+
+    /// Returns an `axum` router with a route for each of the server functions, to make
+    /// requests to directly or to merge into a larger app.
+    pub fn router(&self) -> Router {
+        self.paths.iter().fold(Router::new(), |router, path| {
+            router.route(path, any(handle_server_fn))
+        })
+    }
+
+    /// Starts a call to a server function with the given arguments.
+    pub fn call<T>(&self, args: T) -> TestCall<T> {
+        TestCall {
+            router: self.router(),
+            args,
+            headers: HeaderMap::new(),
+            cookies: Vec::new(),
+            extensions: Extensions::new(),
+        }
+    }
+}
+
+/// A call to a server function that has not been sent yet, created with [`TestServer::call`].
+pub struct TestCall<T> {
+    router: Router,
+    args: T,
+    headers: HeaderMap,
+    cookies: Vec<String>,
+    extensions: Extensions,
+}
+
+impl<T> TestCall<T> {
+    /// Sets a request header, replacing the header set by the codec, if any.
+    ///
+    /// # Panics
+    /// Panics if the name or the value isn't a valid header.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        let name = HeaderName::try_from(name).expect("invalid header name");
+        let value = HeaderValue::try_from(value).expect("invalid header value");
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// Adds a cookie to the request's `Cookie` header.
+    pub fn cookie(mut self, name: &str, value: &str) -> Self {
+        self.cookies.push(format!("{name}={value}"));
+        self
+    }
+
+    /// Adds a request extension, which the server function can read with
+    /// [`extract`](crate::axum::extract) or middleware can read from the request.
+    pub fn extension<E>(mut self, value: E) -> Self
+    where
+        E: Clone + Send + Sync + 'static,
+    {
+        self.extensions.insert(value);
+        self
+    }
+
+    /// Sends the request to the server function and returns its response.
+    ///
+    /// # Panics
+    /// Panics if the arguments can't be encoded into a request, or the cookies aren't a
+    /// valid header.
+    pub async fn send(self) -> TestResponse<T>
+    where
+        T: ServerFn + IntoReq<T::Error, Request<Body>, T::InputEncoding>,
+        T::Error: Display,
+    {
+        let req = <T as IntoReq<T::Error, Request<Body>, T::InputEncoding>>::into_req(
+            self.args,
+            T::PATH,
+            T::OutputEncoding::CONTENT_TYPE,
+        );
+        let mut req = match req {
+            Ok(req) => req,
+            Err(e) => panic!("couldn't encode the arguments of {}: {e}", T::PATH),
+        };
+        req.headers_mut().extend(self.headers);
+        if !self.cookies.is_empty() {
+            let cookies =
+                HeaderValue::try_from(self.cookies.join("; ")).expect("invalid cookie header");
+            req.headers_mut().insert(COOKIE, cookies);
+        }
+        req.extensions_mut().extend(self.extensions);
+
+        let res = match self.router.oneshot(req).await {
+            Ok(res) => res,
+            Err(never) => match never {},
+        };
+        TestResponse {
+            res,
+            ty: PhantomData,
+        }
+    }
+}
+
+/// The response to a [`TestCall`].
+#[derive(Debug)]
+pub struct TestResponse<T> {
+    res: Response<Body>,
+    ty: PhantomData<T>,
+}
+
+impl<T> TestResponse<T> {
+    /// The status code of the response.
+    pub fn status(&self) -> u16 {
+        self.res.status().as_u16()
+    }
+
+    /// Returns the value of the given header, if any.
+    pub fn header(&self, name: &str) -> Option<String> {
+        self.res
+            .headers()
+            .get(name)
+            .map(|value| String::from_utf8_lossy(value.as_bytes()).to_string())
+    }
+
+    /// All of the headers of the response.
+    pub fn headers(&self) -> &HeaderMap {
+        self.res.headers()
+    }
+
+    /// The values of every `Set-Cookie` header in the response.
+    pub fn set_cookies(&self) -> Vec<String> {
+        self.res
+            .headers()
+            .get_all(SET_COOKIE)
+            .iter()
+            .map(|value| String::from_utf8_lossy(value.as_bytes()).to_string())
+            .collect()
+    }
+
+    /// Panics unless the response has the given status code.
+    #[track_caller]
+    pub fn assert_status(&self, status: u16) -> &Self {
+        assert_eq!(self.status(), status, "unexpected status code");
+        self
+    }
+
+    /// Panics unless the response has the given header, with the given value.
+    #[track_caller]
+    pub fn assert_header(&self, name: &str, value: &str) -> &Self {
+        assert_eq!(
+            self.header(name).as_deref(),
+            Some(value),
+            "unexpected value for the {name} header"
+        );
+        self
+    }
+
+    /// Decodes the output of the server function, or the error that it returned, in the
+    /// same way as the client.
+    pub async fn output(self) -> Result<T::Output, ServerFnError<T::Error>>
+    where
+        T: ServerFn,
+        T::Output: FromRes<T::Error, Response<Body>, T::OutputEncoding>,
+        T::Error: DeserializeOwned,
+    {
+        if (400..=599).contains(&self.status()) {
+            Err(read_error(self.res).await)
+        } else {
+            T::Output::from_res(self.res).await
+        }
+    }
+
+    /// Returns the response itself, to check its body directly.
+    pub fn into_response(self) -> Response<Body> {
+        self.res
+    }
+}
@@ -0,0 +1,210 @@
+//! Tracing spans and structured logs for server function calls, enabled with the `tracing`
+//! feature.
+//!
+//! Every call gets a `server_fn` span on the server (in `run_on_server`) and on the client
+//! (in `run_on_client`), with these fields:
+//!
+//! - `server_fn.name`, `server_fn.path`, `server_fn.input` and `server_fn.output`
+//! - `http.request.size`, from the `Content-Length` of the request (on the server only)
+//! - `http.status` and `latency_ms`, once the call has finished
+//! - `error.kind`, if it failed (see [`ServerFnError::kind`])
+//! - `trace_id`, `span_id` and `parent_span_id`
+//!
+//! The client sends a W3C [`traceparent`](https://www.w3.org/TR/trace-context/) header, so
+//! the server span is part of the same trace as the client span. A server function that
+//! calls another server function passes its own trace on in the same way.
+//!
+//! The decoded arguments are logged at the `DEBUG` level. Arguments marked with
+//! `#[server(sensitive)]` are redacted from these logs, and from the `Debug` implementation of
+//! the arguments struct.
+
+use crate::{
+    context::{use_context, ServerContext},
+    error::ServerFnError,
+    random::random_u64,
+    request::Req,
+    ServerFn,
+};
+use serde::Serialize;
+use std::fmt::{self, Display};
+use tracing::{field::Empty, Span};
+
+/// The name of the header that carries the [`TraceContext`] of the caller.
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// The W3C trace context of a server function call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceContext {
+    /// The id of the whole trace, shared by every span in it.
+    pub trace_id: u128,
+    /// The id of this span.
+    pub span_id: u64,
+    /// Whether the caller has chosen to record this trace.
+    pub sampled: bool,
+}
+
+impl TraceContext {
+    /// Starts a new trace.
+    pub fn new_root() -> Self {
+        Self {
+            trace_id: (u128::from(random_id()) << 64) | u128::from(random_id()),
+            span_id: random_id(),
+            sampled: true,
+        }
+    }
+
+    /// Creates the context of a span within this one.
+    pub fn child(&self) -> Self {
+        Self {
+            span_id: random_id(),
+            ..*self
+        }
+    }
+
+    /// Returns the trace context of the server function that is currently running, if any.
+    pub fn current() -> Option<Self> {
+        use_context()
+    }
+
+    /// Parses the value of a `traceparent` header.
+    pub fn parse(header: &str) -> Option<Self> {
+        let mut parts = header.trim().split('-');
+        let version = parts.next()?;
+        let trace_id = parts.next()?;
+        let span_id = parts.next()?;
+        let flags = parts.next()?;
+        // later versions may add more parts, but version 00 must not
+        if version.len() != 2 || version == "ff" || (version == "00" && parts.next().is_some()) {
+            return None;
+        }
+        if trace_id.len() != 32 || span_id.len() != 16 || flags.len() != 2 {
+            return None;
+        }
+        let trace_id = u128::from_str_radix(trace_id, 16).ok()?;
+        let span_id = u64::from_str_radix(span_id, 16).ok()?;
+        let flags = u8::from_str_radix(flags, 16).ok()?;
+        (trace_id != 0 && span_id != 0).then_some(Self {
+            trace_id,
+            span_id,
+            sampled: flags & 1 == 1,
+        })
+    }
+}
+
+/// Formats the context as the value of a `traceparent` header.
+impl Display for TraceContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "00-{:032x}-{:016x}-{:02x}",
+            self.trace_id,
+            self.span_id,
+            u8::from(self.sampled)
+        )
+    }
+}
+
+// ids can't be zero
+fn random_id() -> u64 {
+    random_u64().max(1)
+}
+
+/// The current time in milliseconds, for measuring latency.
+pub(crate) fn now_ms() -> f64 {
+    #[cfg(all(feature = "browser", target_arch = "wasm32"))]
+    {
+        js_sys::Date::now()
+    }
+    #[cfg(not(all(feature = "browser", target_arch = "wasm32")))]
+    {
+        use once_cell::sync::Lazy;
+        use std::time::Instant;
+
+        static START: Lazy<Instant> = Lazy::new(Instant::now);
+        START.elapsed().as_secs_f64() * 1000.0
+    }
+}
+
+fn short_type_name<T: ?Sized>() -> &'static str {
+    let name = std::any::type_name::<T>();
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+/// Creates the span for a call on the server, continuing the caller's trace if the request
+/// has a `traceparent` header, and adds the trace to the context of the call.
+pub(crate) fn server_span<T: ServerFn>(req: &T::ServerRequest, context: &ServerContext) -> Span {
+    let parent = req
+        .header(TRACEPARENT_HEADER)
+        .and_then(|header| TraceContext::parse(&header));
+    let trace = parent
+        .map(|parent| parent.child())
+        .unwrap_or_else(TraceContext::new_root);
+    context.insert(trace);
+    let size = req
+        .header("content-length")
+        .and_then(|size| size.parse::<u64>().ok());
+    tracing::info_span!(
+        "server_fn",
+        server_fn.name = short_type_name::<T>(),
+        server_fn.path = T::PATH,
+        server_fn.input = short_type_name::<T::InputEncoding>(),
+        server_fn.output = short_type_name::<T::OutputEncoding>(),
+        http.request.size = size,
+        http.status = Empty,
+        latency_ms = Empty,
+        error.kind = Empty,
+        trace_id = %format_args!("{:032x}", trace.trace_id),
+        span_id = %format_args!("{:016x}", trace.span_id),
+        parent_span_id = parent.map(|parent| format!("{:016x}", parent.span_id)),
+    )
+}
+
+/// Creates the span for a call on the client, continuing the trace of the server function
+/// that is making the call, if any.
+pub(crate) fn client_span<T: ServerFn>() -> (Span, TraceContext) {
+    let parent = TraceContext::current();
+    let trace = parent
+        .map(|parent| parent.child())
+        .unwrap_or_else(TraceContext::new_root);
+    let span = tracing::info_span!(
+        "server_fn",
+        server_fn.name = short_type_name::<T>(),
+        server_fn.path = T::PATH,
+        server_fn.input = short_type_name::<T::InputEncoding>(),
+        server_fn.output = short_type_name::<T::OutputEncoding>(),
+        http.status = Empty,
+        latency_ms = Empty,
+        error.kind = Empty,
+        trace_id = %format_args!("{:032x}", trace.trace_id),
+        span_id = %format_args!("{:016x}", trace.span_id),
+        parent_span_id = parent.map(|parent| format!("{:016x}", parent.span_id)),
+    );
+    (span, trace)
+}
+
+/// Records how a call finished on the current span, and logs it.
+pub(crate) fn record_outcome<CustErr: Serialize>(
+    status: u16,
+    error: Option<&ServerFnError<CustErr>>,
+    start_ms: f64,
+) {
+    let span = Span::current();
+    let latency_ms = now_ms() - start_ms;
+    span.record("http.status", status);
+    span.record("latency_ms", latency_ms);
+    match error {
+        Some(error) => {
+            span.record("error.kind", error.kind());
+            // the custom error type only needs to be serializable, not `Display`
+            let message = serde_json::to_string(error).unwrap_or_default();
+            tracing::warn!(
+                status,
+                latency_ms,
+                error.kind = error.kind(),
+                error.message = %message,
+                "server function failed"
+            );
+        }
+        None => tracing::debug!(status, latency_ms, "server function finished"),
+    }
+}
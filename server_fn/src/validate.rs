@@ -0,0 +1,168 @@
+//! Validation of server function arguments, after they have been decoded and before the
+//! body of the server function runs.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::{self, Display},
+};
+
+/// Validates the decoded arguments of a server function.
+///
+/// The `#[server]` macro implements this for the arguments struct if any argument has a
+/// validation attribute, like `#[server(length(min = 1, max = 50))]` or
+/// `#[server(range(min = 0, max = 150))]`. It can also be implemented by hand, in which case
+/// `#[server(validate)]` tells the macro to call it.
+///
+/// If validation fails, the server function responds with `422 Unprocessable Entity` and a
+/// [`ServerFnError::Validation`](crate::ServerFnError::Validation) listing each failure.
+pub trait Validate {
+    /// Checks the arguments, returning every rule that they break.
+    fn validate(&self) -> Result<(), ValidationErrors>;
+}
+
+/// A validation rule that one argument failed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldError {
+    /// The name of the argument.
+    pub field: String,
+    /// A description of the rule that it failed.
+    pub message: String,
+}
+
+/// Every validation rule that the arguments of a server function failed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ValidationErrors(pub Vec<FieldError>);
+
+impl ValidationErrors {
+    /// Adds a failed rule for the given argument.
+    pub fn add(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.0.push(FieldError {
+            field: field.into(),
+            message: message.into(),
+        });
+    }
+
+    /// Whether no rules have failed.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The messages for each rule that the given argument failed, for example to show them
+    /// next to the matching form input.
+    pub fn messages<'a>(&'a self, field: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.0
+            .iter()
+            .filter(move |error| error.field == field)
+            .map(|error| error.message.as_str())
+    }
+
+    /// Returns `Ok(())` if no rules have failed, or the errors otherwise.
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, error) in self.0.iter().enumerate() {
+            if idx > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{}: {}", error.field, error.message)?;
+        }
+        Ok(())
+    }
+}
+
+/// A value whose length can be checked with `#[server(length(min = .., max = ..))]`.
+pub trait HasLength {
+    /// The length of the value. For strings, this is the number of characters.
+    fn length(&self) -> usize;
+}
+
+impl HasLength for str {
+    fn length(&self) -> usize {
+        self.chars().count()
+    }
+}
+
+impl HasLength for String {
+    fn length(&self) -> usize {
+        self.as_str().length()
+    }
+}
+
+impl<T> HasLength for [T] {
+    fn length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T> HasLength for Vec<T> {
+    fn length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<K, V, S> HasLength for HashMap<K, V, S> {
+    fn length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T, S> HasLength for HashSet<T, S> {
+    fn length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<K, V> HasLength for BTreeMap<K, V> {
+    fn length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T> HasLength for BTreeSet<T> {
+    fn length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T: HasLength + ?Sized> HasLength for &T {
+    fn length(&self) -> usize {
+        (**self).length()
+    }
+}
+
+/// Checks that the length of a value is within the given bounds.
+pub fn length<T: HasLength + ?Sized>(
+    value: &T,
+    min: Option<usize>,
+    max: Option<usize>,
+) -> Result<(), String> {
+    let length = value.length();
+    match (min, max) {
+        (Some(min), _) if length < min => Err(format!("must have a length of at least {min}")),
+        (_, Some(max)) if length > max => Err(format!("must have a length of at most {max}")),
+        _ => Ok(()),
+    }
+}
+
+/// Checks that a value is within the given bounds.
+pub fn range<T: PartialOrd + Display>(
+    value: &T,
+    min: Option<T>,
+    max: Option<T>,
+) -> Result<(), String> {
+    match (min, max) {
+        (Some(min), _) if *value < min => Err(format!("must be at least {min}")),
+        (_, Some(max)) if *value > max => Err(format!("must be at most {max}")),
+        _ => Ok(()),
+    }
+}
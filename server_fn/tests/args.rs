@@ -0,0 +1,103 @@
+use serde::{de, Deserialize, Deserializer, Serialize};
+use server_fn_macro_default::server;
+use server_fns::{
+    axum_export::{
+        body::{to_bytes, Body},
+        http::Request,
+    },
+    testing::TestServer,
+    ServerFn, ServerFnError,
+};
+use tower::ServiceExt;
+
+#[server]
+pub async fn greet(name: String, age: u32) -> Result<String, ServerFnError> {
+    Ok(format!("{name} ({age})"))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Order {
+    pub item: Item,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Item {
+    pub name: String,
+    pub count: u32,
+}
+
+#[server(input = Json)]
+pub async fn place(order: Order) -> Result<(), ServerFnError> {
+    _ = order;
+    Ok(())
+}
+
+/// A code that is never valid, with an error that reads like a missing field.
+#[derive(Debug, Clone, Serialize)]
+pub struct Code(String);
+
+impl<'de> Deserialize<'de> for Code {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?;
+        Err(de::Error::custom("missing field `checksum`"))
+    }
+}
+
+#[server(input = Json)]
+pub async fn redeem(code: Code) -> Result<(), ServerFnError> {
+    _ = code;
+    Ok(())
+}
+
+/// Sends a body that the client wouldn't, and returns the error that it gets.
+async fn send_raw(path: &str, content_type: &str, body: &str) -> ServerFnError {
+    let req = Request::post(path)
+        .header("content-type", content_type)
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let res = TestServer::new().router().oneshot(req).await.unwrap();
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn names_missing_arguments() {
+    let err = send_raw(Greet::PATH, "application/x-www-form-urlencoded", "age=20").await;
+    assert!(matches!(err, ServerFnError::MissingArg(arg) if arg == "name"));
+
+    let err = send_raw(
+        Place::PATH,
+        "application/json",
+        r#"{"order":{"item":{"count":1}}}"#,
+    )
+    .await;
+    assert!(matches!(err, ServerFnError::MissingArg(arg) if arg == "order.item.name"));
+}
+
+#[tokio::test]
+async fn only_names_fields_that_are_missing() {
+    let err = send_raw(Redeem::PATH, "application/json", r#"{"code":"abc"}"#).await;
+    let ServerFnError::Args(message) = err else {
+        panic!("expected an arguments error, got {err:?}");
+    };
+    assert_eq!(message, "code: missing field `checksum`");
+}
+
+#[tokio::test]
+async fn describes_invalid_arguments() {
+    let err = send_raw(
+        Place::PATH,
+        "application/json",
+        r#"{"order":{"item":{"name":"tea","count":"two"}}}"#,
+    )
+    .await;
+    let ServerFnError::Args(message) = err else {
+        panic!("expected an arguments error, got {err:?}");
+    };
+    assert!(
+        message.starts_with("order.item.count: invalid type"),
+        "{message}"
+    );
+    // the position in the body means nothing to the caller
+    assert!(!message.contains("line"), "{message}");
+}
@@ -0,0 +1,219 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::{json, Value};
+use server_fn_macro_default::server;
+use server_fns::{
+    axum::{extract, handle_batch},
+    axum_export::{
+        body::{to_bytes, Body},
+        http::{HeaderMap, Request, Response},
+        routing::post,
+        Router,
+    },
+    batch::{BatchClient, DEFAULT_BATCH_PATH, MAX_BATCH_SIZE},
+    client::Client,
+    testing::TestServer,
+    ServerFn, ServerFnError,
+};
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    task::Poll,
+};
+use tower::ServiceExt;
+
+fn router() -> Router {
+    TestServer::new()
+        .router()
+        .route(DEFAULT_BATCH_PATH, post(handle_batch))
+}
+
+static BATCHES: [AtomicUsize; 3] = [const { AtomicUsize::new(0) }; 3];
+
+/// Sends requests to the router in the same process, counting the batches that it sends.
+///
+/// Each `QUEUE` is a different client, so that tests running at the same time don't share
+/// batches.
+pub struct TestClient<const QUEUE: usize>;
+
+impl<CustErr, const QUEUE: usize> Client<CustErr> for TestClient<QUEUE> {
+    type Request = Request<Body>;
+    type Response = Response<Body>;
+
+    async fn send(req: Self::Request) -> Result<Self::Response, ServerFnError<CustErr>> {
+        if req.uri().path() == DEFAULT_BATCH_PATH {
+            BATCHES[QUEUE].fetch_add(1, Ordering::SeqCst);
+        }
+        Ok(router().oneshot(req).await.unwrap())
+    }
+}
+
+#[server(client = BatchClient<TestClient<0>>)]
+pub async fn double(x: i32) -> Result<i32, ServerFnError> {
+    Ok(x * 2)
+}
+
+#[server(client = BatchClient<TestClient<1>>)]
+pub async fn triple(x: i32) -> Result<i32, ServerFnError> {
+    Ok(x * 3)
+}
+
+#[server(client = BatchClient<TestClient<2>>)]
+pub async fn quadruple(x: i32) -> Result<i32, ServerFnError> {
+    Ok(x * 4)
+}
+
+#[server]
+pub async fn echo_headers() -> Result<(Option<String>, Option<String>), ServerFnError> {
+    let headers = extract::<HeaderMap>().await?;
+    let header = |name| {
+        headers
+            .get(name)
+            .map(|value: &_| value.to_str().unwrap().to_string())
+    };
+    Ok((header("cookie"), header("idempotency-key")))
+}
+
+fn entry(path: &str, body: &str, headers: &[(&str, &str)]) -> Value {
+    let mut all = vec![
+        ("content-type", "application/x-www-form-urlencoded"),
+        ("accept", "application/json"),
+    ];
+    all.extend_from_slice(headers);
+    json!({
+        "method": "POST",
+        "path": path,
+        "query": null,
+        "headers": all,
+        "body": STANDARD.encode(body),
+    })
+}
+
+/// Sends a batch with the given content type and headers, and returns the status and body of
+/// the response.
+async fn send_batch(
+    content_type: &str,
+    headers: &[(&str, &str)],
+    entries: &[Value],
+) -> (u16, String) {
+    let mut req = Request::post(DEFAULT_BATCH_PATH).header("content-type", content_type);
+    for (name, value) in headers {
+        req = req.header(*name, *value);
+    }
+    let req = req
+        .body(Body::from(serde_json::to_string(entries).unwrap()))
+        .unwrap();
+    let res = router().oneshot(req).await.unwrap();
+    let status = res.status().as_u16();
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+/// The status and decoded body of each call in the response to a batch.
+fn results(body: &str) -> Vec<(u16, String)> {
+    serde_json::from_str::<Vec<Value>>(body)
+        .unwrap()
+        .into_iter()
+        .map(|result| {
+            let body = STANDARD.decode(result["body"].as_str().unwrap()).unwrap();
+            (
+                result["status"].as_u64().unwrap() as u16,
+                String::from_utf8(body).unwrap(),
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn sends_calls_in_the_same_tick_together() {
+    let before = BATCHES[0].load(Ordering::SeqCst);
+    let (a, b, c) = futures::join!(
+        Double { x: 1 }.run_on_client(),
+        Double { x: 2 }.run_on_client(),
+        Double { x: 3 }.run_on_client(),
+    );
+    assert_eq!((a.unwrap(), b.unwrap(), c.unwrap()), (2, 4, 6));
+    assert_eq!(BATCHES[0].load(Ordering::SeqCst), before + 1);
+}
+
+#[tokio::test]
+async fn splits_large_batches() {
+    let calls = (0..MAX_BATCH_SIZE as i32 * 2 + 2).map(|x| Triple { x }.run_on_client());
+    let results = futures::future::join_all(calls).await;
+    for (x, result) in results.into_iter().enumerate() {
+        assert_eq!(result.unwrap(), x as i32 * 3);
+    }
+    assert_eq!(BATCHES[1].load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn sends_the_batch_if_the_first_call_is_dropped() {
+    let mut first = Box::pin(Quadruple { x: 1 }.run_on_client());
+    let mut second = Box::pin(Quadruple { x: 2 }.run_on_client());
+    assert!(futures::poll!(first.as_mut()).is_pending());
+    let polled = futures::poll!(second.as_mut());
+    drop(first);
+    let second = match polled {
+        Poll::Ready(res) => res,
+        Poll::Pending => second.await,
+    };
+    assert_eq!(second.unwrap(), 8);
+}
+
+#[tokio::test]
+async fn runs_each_call() {
+    let (status, body) = send_batch(
+        "application/json",
+        &[],
+        &[
+            entry(Double::PATH, "x=1", &[]),
+            entry(Double::PATH, "x=oops", &[]),
+            entry("/api/missing", "", &[]),
+        ],
+    )
+    .await;
+    assert_eq!(status, 200);
+    let results = results(&body);
+    assert_eq!(results[0], (200, "2".into()));
+    assert_eq!(results[1].0, 500);
+    assert!(results[1].1.contains(r#""type":"Args""#));
+    assert_eq!(results[2].0, 500);
+    assert!(results[2].1.contains("no server function at /api/missing"));
+}
+
+#[tokio::test]
+async fn rejects_batches_that_arent_json() {
+    let entries = [entry(Double::PATH, "x=1", &[])];
+    let (status, _) = send_batch("text/plain", &[], &entries).await;
+    assert_eq!(status, 415);
+    let (status, _) = send_batch("application/json; charset=utf-8", &[], &entries).await;
+    assert_eq!(status, 200);
+}
+
+#[tokio::test]
+async fn rejects_too_many_calls() {
+    let entries = vec![entry(Double::PATH, "x=1", &[]); MAX_BATCH_SIZE + 1];
+    let (status, body) = send_batch("application/json", &[], &entries).await;
+    assert_eq!(status, 500);
+    assert!(body.contains(&format!("at most {MAX_BATCH_SIZE} calls")));
+}
+
+#[tokio::test]
+async fn calls_only_set_their_own_headers() {
+    let (status, body) = send_batch(
+        "application/json",
+        &[("cookie", "session=outer"), ("idempotency-key", "outer")],
+        &[
+            entry(EchoHeaders::PATH, "", &[("idempotency-key", "inner")]),
+            entry(EchoHeaders::PATH, "", &[("cookie", "session=inner")]),
+        ],
+    )
+    .await;
+    assert_eq!(status, 200);
+    let results = results(&body);
+    assert_eq!(
+        results[0],
+        (200, r#"["session=outer","outer"]"#.into()),
+        "a call can't replace a header of the batch request"
+    );
+    assert_eq!(results[1].0, 500);
+    assert!(results[1].1.contains("can't set the cookie header"));
+}
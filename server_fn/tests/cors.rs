@@ -0,0 +1,29 @@
+use server_fns::{axum::set_cors, cors::Cors};
+
+#[test]
+fn allows_any_origin_with_a_wildcard() {
+    let cors = Cors::new().allow_any_origin();
+    let headers = cors.response_headers(Some("https://app.example"));
+    assert!(headers.contains(&("access-control-allow-origin", "*".into())));
+    assert!(!headers
+        .iter()
+        .any(|(name, _)| *name == "access-control-allow-credentials"));
+}
+
+#[test]
+fn echoes_allowed_origins_with_credentials() {
+    let cors = Cors::new()
+        .allow_origin("https://app.example")
+        .allow_credentials(true);
+    let headers = cors.preflight_headers(Some("https://app.example"), "POST");
+    assert!(headers.contains(&("access-control-allow-origin", "https://app.example".into())));
+    assert!(headers.contains(&("access-control-allow-credentials", "true".into())));
+    let headers = cors.preflight_headers(Some("https://evil.example"), "POST");
+    assert_eq!(headers, [("vary", "Origin".into())]);
+}
+
+#[test]
+#[should_panic(expected = "can't be combined with `allow_any_origin()`")]
+fn rejects_credentials_from_any_origin() {
+    set_cors(Cors::new().allow_any_origin().allow_credentials(true));
+}
@@ -0,0 +1,146 @@
+use server_fn_macro_default::server;
+use server_fns::{
+    axum_export::{body::Body, http::Request},
+    codec::TextStream,
+    middleware::csrf::CsrfLayer,
+    testing::TestServer,
+    ServerFn, ServerFnError,
+};
+use tower::ServiceExt;
+
+#[server]
+#[middleware(CsrfLayer::new())]
+pub async fn post_fn() -> Result<(), ServerFnError> {
+    Ok(())
+}
+
+#[server(input = GetUrl)]
+#[middleware(CsrfLayer::new())]
+pub async fn get_fn() -> Result<(), ServerFnError> {
+    Ok(())
+}
+
+#[server]
+#[middleware(CsrfLayer::new().trust_origin("https://app.example"))]
+pub async fn trusting_fn() -> Result<(), ServerFnError> {
+    Ok(())
+}
+
+#[server(input = WebsocketText, output = WebsocketText)]
+#[middleware(CsrfLayer::new())]
+pub async fn socket(input: TextStream) -> Result<TextStream, ServerFnError> {
+    Ok(input)
+}
+
+#[tokio::test]
+async fn checks_sec_fetch_site() {
+    let server = TestServer::new();
+    for (site, status) in [
+        ("same-origin", 200),
+        ("none", 200),
+        ("cross-site", 403),
+        ("same-site", 403),
+    ] {
+        let res = server
+            .call(PostFn {})
+            .header("sec-fetch-site", site)
+            .send()
+            .await;
+        res.assert_status(status);
+    }
+}
+
+#[tokio::test]
+async fn checks_get_calls() {
+    let res = TestServer::new()
+        .call(GetFn {})
+        .header("sec-fetch-site", "cross-site")
+        .send()
+        .await;
+    res.assert_status(403);
+    assert!(matches!(
+        res.output().await,
+        Err(ServerFnError::Forbidden(_))
+    ));
+}
+
+#[tokio::test]
+async fn compares_the_origin_with_the_host() {
+    let server = TestServer::new();
+    let call = |origin: &str, host: &str| {
+        server
+            .call(PostFn {})
+            .header("origin", origin)
+            .header("host", host)
+            .send()
+    };
+    call("https://example.com", "example.com")
+        .await
+        .assert_status(200);
+    call("https://evil.example", "example.com")
+        .await
+        .assert_status(403);
+    // without a proxy saying otherwise, only loopback hosts are served over http
+    call("http://example.com", "example.com")
+        .await
+        .assert_status(403);
+    call("http://localhost:3000", "localhost:3000")
+        .await
+        .assert_status(200);
+}
+
+#[tokio::test]
+async fn uses_the_forwarded_scheme() {
+    let res = TestServer::new()
+        .call(PostFn {})
+        .header("origin", "http://localhost:3000")
+        .header("host", "localhost:3000")
+        .header("x-forwarded-proto", "https")
+        .send()
+        .await;
+    res.assert_status(403);
+}
+
+#[tokio::test]
+async fn allows_calls_without_an_origin() {
+    TestServer::new()
+        .call(PostFn {})
+        .send()
+        .await
+        .assert_status(200);
+}
+
+#[tokio::test]
+async fn allows_trusted_origins() {
+    let server = TestServer::new();
+    let call = |origin: &str| {
+        server
+            .call(TrustingFn {})
+            .header("origin", origin)
+            .header("sec-fetch-site", "cross-site")
+            .send()
+    };
+    call("https://app.example").await.assert_status(200);
+    call("https://evil.example").await.assert_status(403);
+}
+
+#[tokio::test]
+async fn checks_the_origin_of_websockets() {
+    // WebSocket server functions can't be called over HTTP, so the upgrade is sent directly
+    let upgrade = |origin: &str| {
+        let req = Request::get(Socket::PATH)
+            .header("connection", "upgrade")
+            .header("upgrade", "websocket")
+            .header("sec-websocket-version", "13")
+            .header("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==")
+            .header("host", "example.com")
+            // browsers send this for WebSockets from any page
+            .header("sec-fetch-site", "same-origin")
+            .header("origin", origin)
+            .body(Body::empty())
+            .unwrap();
+        TestServer::new().router().oneshot(req)
+    };
+    assert_eq!(upgrade("https://evil.example").await.unwrap().status(), 403);
+    assert_ne!(upgrade("https://example.com").await.unwrap().status(), 403);
+}
@@ -0,0 +1,151 @@
+use server_fn_macro_default::server;
+use server_fns::{
+    context::use_context,
+    middleware::idempotency::{
+        Claim, Header, IdempotencyLayer, IdempotencyStore, MemoryStore, IDEMPOTENT_REPLAY_HEADER,
+    },
+    response::ResponseOptions,
+    testing::TestServer,
+    ServerFnError,
+};
+use std::{
+    sync::atomic::{AtomicU32, Ordering},
+    thread,
+    time::Duration,
+};
+
+static ORDERS: AtomicU32 = AtomicU32::new(0);
+
+/// Returns a new order number each time it runs, and sets a cookie.
+#[server]
+#[middleware(IdempotencyLayer::new().key_by(Header("x-user")).max_body(64))]
+pub async fn place_order(item: String) -> Result<u32, ServerFnError> {
+    _ = item;
+    let options = use_context::<ResponseOptions>().unwrap();
+    options.set_cookie("last-order=1");
+    Ok(ORDERS.fetch_add(1, Ordering::SeqCst))
+}
+
+static ATTEMPTS: AtomicU32 = AtomicU32::new(0);
+
+/// Fails with `403 Forbidden` on its first call.
+#[server]
+#[middleware(IdempotencyLayer::new().key_by(Header("x-user")))]
+pub async fn forbidden_once() -> Result<u32, ServerFnError> {
+    match ATTEMPTS.fetch_add(1, Ordering::SeqCst) {
+        0 => Err(ServerFnError::Forbidden("not yet".into())),
+        attempt => Ok(attempt),
+    }
+}
+
+fn order(user: &str, key: &str, item: &str) -> server_fns::testing::TestCall<PlaceOrder> {
+    TestServer::new()
+        .call(PlaceOrder { item: item.into() })
+        .header("x-user", user)
+        .header("idempotency-key", key)
+}
+
+#[tokio::test]
+async fn replays_the_stored_response() {
+    let first = order("a", "replays", "tea").send().await;
+    first.assert_status(200);
+    assert_eq!(first.header(IDEMPOTENT_REPLAY_HEADER), None);
+    assert_eq!(first.set_cookies(), ["last-order=1"]);
+    let number = first.output().await.unwrap();
+
+    let second = order("a", "replays", "tea").send().await;
+    second
+        .assert_status(200)
+        .assert_header(IDEMPOTENT_REPLAY_HEADER, "true");
+    assert!(
+        second.set_cookies().is_empty(),
+        "cookies are never replayed"
+    );
+    assert_eq!(second.output().await.unwrap(), number);
+}
+
+#[tokio::test]
+async fn rejects_a_key_reused_with_other_arguments() {
+    order("a", "other-arguments", "tea")
+        .send()
+        .await
+        .assert_status(200);
+    let res = order("a", "other-arguments", "coffee").send().await;
+    res.assert_status(409);
+    assert!(matches!(
+        res.output().await,
+        Err(ServerFnError::Conflict(_))
+    ));
+}
+
+#[tokio::test]
+async fn scopes_keys_to_the_client() {
+    let a = order("a", "scoped", "tea")
+        .send()
+        .await
+        .output()
+        .await
+        .unwrap();
+    let res = order("b", "scoped", "tea").send().await;
+    assert_eq!(res.header(IDEMPOTENT_REPLAY_HEADER), None);
+    assert_ne!(res.output().await.unwrap(), a);
+}
+
+#[tokio::test]
+async fn rejects_clients_that_cant_be_identified() {
+    let res = TestServer::new()
+        .call(PlaceOrder { item: "tea".into() })
+        .header("idempotency-key", "anonymous")
+        .send()
+        .await;
+    res.assert_status(500);
+    assert!(matches!(
+        res.output().await,
+        Err(ServerFnError::UnidentifiedClient(_))
+    ));
+}
+
+#[tokio::test]
+async fn runs_calls_without_a_key_every_time() {
+    let call = || {
+        TestServer::new()
+            .call(PlaceOrder { item: "tea".into() })
+            .header("x-user", "a")
+            .send()
+    };
+    let first = call().await.output().await.unwrap();
+    let second = call().await.output().await.unwrap();
+    assert_ne!(first, second);
+}
+
+#[tokio::test]
+async fn does_not_store_auth_failures() {
+    let call = || {
+        TestServer::new()
+            .call(ForbiddenOnce {})
+            .header("x-user", "a")
+            .header("idempotency-key", "forbidden")
+            .send()
+    };
+    call().await.assert_status(403);
+    let res = call().await;
+    res.assert_status(200);
+    assert_eq!(res.header(IDEMPOTENT_REPLAY_HEADER), None);
+}
+
+#[tokio::test]
+async fn limits_the_buffered_body() {
+    let res = order("a", "large", &"x".repeat(100)).send().await;
+    res.assert_status(413);
+}
+
+#[tokio::test]
+async fn frees_keys_whose_call_never_finished() {
+    let store = MemoryStore::new();
+    let lease = Duration::from_millis(20);
+    assert_eq!(store.claim("key", lease).await, Claim::Claimed);
+    assert_eq!(store.claim("key", lease).await, Claim::InProgress);
+    // the first call died without completing or releasing the key
+    thread::sleep(lease);
+    assert_eq!(store.claim("key", lease).await, Claim::Claimed);
+}
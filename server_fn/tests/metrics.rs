@@ -0,0 +1,44 @@
+use server_fn_macro_default::server;
+use server_fns::{
+    middleware::{
+        metrics::{Metrics, MetricsLayer},
+        rate_limit::{Header, Quota, RateLimitLayer},
+    },
+    testing::TestServer,
+    ServerFnError,
+};
+use std::sync::{Arc, LazyLock};
+
+static METRICS: LazyLock<Arc<Metrics>> = LazyLock::new(Default::default);
+
+#[server]
+#[middleware(MetricsLayer::with_metrics(Arc::clone(&METRICS)))]
+pub async fn fails() -> Result<(), ServerFnError> {
+    Err(ServerFnError::ServerError("failed".into()))
+}
+
+#[server]
+#[middleware(RateLimitLayer::new(Quota::per_minute(1)).key_by(Header("x-client")))]
+#[middleware(MetricsLayer::with_metrics(Arc::clone(&METRICS)))]
+pub async fn limited() -> Result<(), ServerFnError> {
+    Ok(())
+}
+
+#[tokio::test]
+async fn counts_errors_from_server_functions_and_middleware() {
+    let server = TestServer::new();
+    server.call(Fails {}).send().await.assert_status(500);
+    for status in [200, 429] {
+        server
+            .call(Limited {})
+            .header("x-client", "a")
+            .send()
+            .await
+            .assert_status(status);
+    }
+
+    let rendered = METRICS.render();
+    assert!(rendered.contains("server_fn_calls_total{path=\"/api/fails"));
+    assert!(rendered.contains("kind=\"ServerError\"} 1"));
+    assert!(rendered.contains("kind=\"RateLimited\"} 1"));
+}
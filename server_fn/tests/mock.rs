@@ -0,0 +1,49 @@
+use server_fns::{
+    axum_export::{
+        body::{to_bytes, Body},
+        http::Request,
+    },
+    mock::MockServer,
+    replay::Fixtures,
+    ServerFnError,
+};
+
+const FIXTURES: &str = r#"{
+    "calls": [
+        {
+            "method": "POST",
+            "path": "/api/get_user",
+            "body": { "text": "id=1" },
+            "response": { "status": 200, "body": { "text": "\"Ada\"" } }
+        }
+    ]
+}"#;
+
+async fn call(mock: &MockServer, body: &'static str) -> (u16, String) {
+    let req = Request::post("/api/get_user")
+        .header("content-type", "application/x-www-form-urlencoded")
+        .body(Body::from(body))
+        .unwrap();
+    let res = mock.handle(req).await;
+    let status = res.status().as_u16();
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn answers_calls_with_matching_fixtures() {
+    let mock = MockServer::new().fixtures(Fixtures::from_json(FIXTURES).unwrap());
+    assert_eq!(call(&mock, "id=1").await, (200, "\"Ada\"".to_string()));
+}
+
+#[tokio::test]
+async fn rejects_calls_without_a_matching_fixture() {
+    let mock = MockServer::new().fixtures(Fixtures::from_json(FIXTURES).unwrap());
+    let (status, body) = call(&mock, "id=2").await;
+    assert_eq!(status, 500);
+    assert!(matches!(
+        serde_json::from_str::<ServerFnError>(&body).unwrap(),
+        ServerFnError::Request(message)
+            if message == "no recorded call to /api/get_user matches these arguments"
+    ));
+}
@@ -0,0 +1,99 @@
+use server_fn_macro_default::server;
+use server_fns::{
+    axum_export::extract::ConnectInfo,
+    middleware::rate_limit::{Header, Quota, RateLimitLayer},
+    testing::TestServer,
+    ServerFnError,
+};
+use std::net::SocketAddr;
+
+#[server]
+#[middleware(RateLimitLayer::new(Quota::per_minute(2)).key_by(Header("x-client")))]
+pub async fn by_header() -> Result<(), ServerFnError> {
+    Ok(())
+}
+
+#[server]
+#[middleware(RateLimitLayer::new(Quota::per_minute(1)))]
+pub async fn by_address() -> Result<(), ServerFnError> {
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_calls_over_the_quota() {
+    let server = TestServer::new();
+    for _ in 0..2 {
+        let res = server
+            .call(ByHeader {})
+            .header("x-client", "a")
+            .send()
+            .await;
+        res.assert_status(200);
+    }
+
+    let res = server
+        .call(ByHeader {})
+        .header("x-client", "a")
+        .send()
+        .await;
+    res.assert_status(429);
+    let retry_after = res.header("retry-after").unwrap().parse::<u64>().unwrap();
+    assert!((1..=30).contains(&retry_after));
+    assert!(matches!(
+        res.output().await,
+        Err(ServerFnError::RateLimited(secs)) if secs == retry_after
+    ));
+}
+
+#[tokio::test]
+async fn gives_each_client_its_own_bucket() {
+    let server = TestServer::new();
+    for client in ["b", "c"] {
+        for _ in 0..2 {
+            let res = server
+                .call(ByHeader {})
+                .header("x-client", client)
+                .send()
+                .await;
+            res.assert_status(200);
+        }
+    }
+}
+
+#[tokio::test]
+async fn rejects_clients_that_cant_be_identified() {
+    let res = TestServer::new().call(ByHeader {}).send().await;
+    res.assert_status(500);
+    assert!(matches!(
+        res.output().await,
+        Err(ServerFnError::UnidentifiedClient(_))
+    ));
+
+    // without `into_make_service_with_connect_info`, there is no address
+    let res = TestServer::new().call(ByAddress {}).send().await;
+    res.assert_status(500);
+}
+
+#[tokio::test]
+async fn identifies_clients_by_their_address() {
+    let server = TestServer::new();
+    let addr = |ip| ConnectInfo(SocketAddr::from((ip, 1234)));
+    let res = server
+        .call(ByAddress {})
+        .extension(addr([10, 0, 0, 1]))
+        .send()
+        .await;
+    res.assert_status(200);
+    let res = server
+        .call(ByAddress {})
+        .extension(addr([10, 0, 0, 1]))
+        .send()
+        .await;
+    res.assert_status(429);
+    let res = server
+        .call(ByAddress {})
+        .extension(addr([10, 0, 0, 2]))
+        .send()
+        .await;
+    res.assert_status(200);
+}
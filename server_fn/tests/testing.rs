@@ -0,0 +1,134 @@
+use serde::{Deserialize, Serialize, Serializer};
+use server_fn_macro_default::server;
+use server_fns::{
+    axum::extract,
+    axum_export::{http::HeaderMap, Extension},
+    context::use_context,
+    response::ResponseOptions,
+    testing::TestServer,
+    ServerFnError,
+};
+
+#[server]
+pub async fn add(a: i32, b: i32) -> Result<i32, ServerFnError> {
+    Ok(a + b)
+}
+
+#[server]
+pub async fn echo_headers() -> Result<(Option<String>, Option<String>), ServerFnError> {
+    let headers = extract::<HeaderMap>().await?;
+    let header = |name| {
+        headers
+            .get(name)
+            .map(|value: &_| value.to_str().unwrap().to_string())
+    };
+    Ok((header("x-test"), header("cookie")))
+}
+
+#[derive(Clone)]
+struct Name(&'static str);
+
+#[server]
+pub async fn greet() -> Result<String, ServerFnError> {
+    let Extension(Name(name)) = extract::<Extension<Name>>().await?;
+    Ok(format!("hello, {name}"))
+}
+
+#[server]
+pub async fn with_options() -> Result<(), ServerFnError> {
+    let options = use_context::<ResponseOptions>().unwrap();
+    options.insert_header("cache-control", "no-store");
+    options.set_cookie("session=abc");
+    options.set_cookie("theme=dark");
+    Ok(())
+}
+
+#[server]
+pub async fn forbidden() -> Result<(), ServerFnError> {
+    Err(ServerFnError::Forbidden("not signed in".into()))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BadArg;
+
+impl Serialize for BadArg {
+    fn serialize<S: Serializer>(&self, _serializer: S) -> Result<S::Ok, S::Error> {
+        Err(serde::ser::Error::custom("this argument can't be encoded"))
+    }
+}
+
+#[server]
+pub async fn unencodable(arg: BadArg) -> Result<(), ServerFnError> {
+    _ = arg;
+    Ok(())
+}
+
+#[tokio::test]
+async fn decodes_the_output() {
+    let res = TestServer::new().call(Add { a: 1, b: 2 }).send().await;
+    res.assert_status(200);
+    assert_eq!(res.output().await.unwrap(), 3);
+}
+
+#[tokio::test]
+async fn sends_headers_and_cookies() {
+    let res = TestServer::new()
+        .call(EchoHeaders {})
+        .header("x-test", "1")
+        .cookie("session", "abc")
+        .cookie("theme", "dark")
+        .send()
+        .await;
+    assert_eq!(
+        res.output().await.unwrap(),
+        (Some("1".into()), Some("session=abc; theme=dark".into()))
+    );
+}
+
+#[tokio::test]
+async fn sends_extensions() {
+    let res = TestServer::new()
+        .call(Greet {})
+        .extension(Name("tests"))
+        .send()
+        .await;
+    assert_eq!(res.output().await.unwrap(), "hello, tests");
+}
+
+#[tokio::test]
+async fn reads_response_headers_and_cookies() {
+    let res = TestServer::new().call(WithOptions {}).send().await;
+    res.assert_status(200)
+        .assert_header("cache-control", "no-store");
+    assert_eq!(res.set_cookies(), ["session=abc", "theme=dark"]);
+}
+
+#[tokio::test]
+async fn decodes_errors() {
+    let res = TestServer::new().call(Forbidden {}).send().await;
+    res.assert_status(403);
+    assert!(matches!(
+        res.output().await,
+        Err(ServerFnError::Forbidden(message)) if message == "not signed in"
+    ));
+}
+
+#[tokio::test]
+async fn routes_registered_server_functions() {
+    let server = TestServer::empty().register::<Add>();
+    let res = server.call(Add { a: 2, b: 2 }).send().await;
+    assert_eq!(res.output().await.unwrap(), 4);
+
+    // only registered server functions have routes
+    let res = server.call(Greet {}).send().await;
+    res.assert_status(404);
+}
+
+#[tokio::test]
+#[should_panic(expected = "this argument can't be encoded")]
+async fn panics_with_the_encoding_error() {
+    TestServer::new()
+        .call(Unencodable { arg: BadArg })
+        .send()
+        .await;
+}
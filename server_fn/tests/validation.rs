@@ -0,0 +1,84 @@
+use server_fn_macro_default::server;
+use server_fns::{
+    testing::TestServer,
+    validate::{Validate, ValidationErrors},
+    ServerFnError,
+};
+
+#[server]
+pub async fn sign_up(
+    #[server(length(min = 3, max = 10))] name: String,
+    #[server(range(min = 13, max = 150))] age: u32,
+) -> Result<String, ServerFnError> {
+    Ok(format!("{name} ({age})"))
+}
+
+#[server(validate)]
+pub async fn transfer(from: String, to: String) -> Result<(), ServerFnError> {
+    _ = (from, to);
+    Ok(())
+}
+
+impl Validate for Transfer {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::default();
+        if self.from == self.to {
+            errors.add("to", "must be a different account");
+        }
+        errors.into_result()
+    }
+}
+
+#[tokio::test]
+async fn runs_valid_calls() {
+    let res = TestServer::new()
+        .call(SignUp {
+            name: "ada".into(),
+            age: 36,
+        })
+        .send()
+        .await;
+    assert_eq!(res.output().await.unwrap(), "ada (36)");
+}
+
+#[tokio::test]
+async fn rejects_each_invalid_argument() {
+    let res = TestServer::new()
+        .call(SignUp {
+            name: "a".into(),
+            age: 200,
+        })
+        .send()
+        .await;
+    res.assert_status(422);
+    let Err(ServerFnError::Validation(errors)) = res.output().await else {
+        panic!("expected a validation error");
+    };
+    assert_eq!(
+        errors.messages("name").collect::<Vec<_>>(),
+        ["must have a length of at least 3"]
+    );
+    assert_eq!(
+        errors.messages("age").collect::<Vec<_>>(),
+        ["must be at most 150"]
+    );
+}
+
+#[tokio::test]
+async fn runs_hand_written_validation() {
+    let res = TestServer::new()
+        .call(Transfer {
+            from: "a".into(),
+            to: "a".into(),
+        })
+        .send()
+        .await;
+    res.assert_status(422);
+    let Err(ServerFnError::Validation(errors)) = res.output().await else {
+        panic!("expected a validation error");
+    };
+    assert_eq!(
+        errors.messages("to").collect::<Vec<_>>(),
+        ["must be a different account"]
+    );
+}
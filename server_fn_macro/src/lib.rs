@@ -72,14 +72,30 @@ pub fn server_macro_impl(
         input,
         output,
         fn_path,
+        check,
+        validate,
+        guards,
+        body_limit,
+        timeout,
+        max_concurrent,
+        queue_timeout,
+        client,
     } = args;
     let prefix = prefix.unwrap_or_else(|| Literal::string(default_path));
     let fn_path = fn_path.unwrap_or_else(|| Literal::string(""));
     let input = input.unwrap_or_else(|| syn::parse_quote!(PostUrl));
     let input_is_rkyv = input == "Rkyv";
     let input_is_multipart = input == "MultipartFormData";
+    let input_is_websocket = input == "Websocket" || input == "WebsocketText";
     let input = codec_ident(server_fn_path.as_ref(), input);
     let output = output.unwrap_or_else(|| syn::parse_quote!(Json));
+    let output_is_websocket = output == "Websocket" || output == "WebsocketText";
+    if input_is_websocket != output_is_websocket {
+        return Err(syn::Error::new(
+            Span::call_site(),
+            "`Websocket` and `WebsocketText` must be used for both the input and the output",
+        ));
+    }
     let output = codec_ident(server_fn_path.as_ref(), output);
     // default to PascalCase version of function name if no struct name given
     let struct_name = struct_name.unwrap_or_else(|| {
@@ -98,6 +114,10 @@ pub fn server_macro_impl(
     let block = body.block;
     let attrs = body.attrs;
 
+    // validation rules for args, as (field, kind, min, max)
+    let mut validations = Vec::new();
+    // args that should be redacted from logs
+    let mut sensitive_fields = Vec::new();
     let fields = body
         .inputs
         .iter_mut()
@@ -123,10 +143,33 @@ pub fn server_macro_impl(
                     if meta.path.is_ident("default") && meta.input.is_empty() {
                         default = true;
                         Ok(())
+                    } else if meta.path.is_ident("sensitive") && meta.input.is_empty() {
+                        sensitive_fields.push(typed_arg.pat.clone());
+                        Ok(())
+                    } else if meta.path.is_ident("length") || meta.path.is_ident("range") {
+                        let kind = meta.path.get_ident().cloned().unwrap();
+                        let mut min = None;
+                        let mut max = None;
+                        meta.parse_nested_meta(|bound| {
+                            if bound.path.is_ident("min") {
+                                min = Some(bound.value()?.parse::<syn::Expr>()?);
+                                Ok(())
+                            } else if bound.path.is_ident("max") {
+                                max = Some(bound.value()?.parse::<syn::Expr>()?);
+                                Ok(())
+                            } else {
+                                Err(bound.error("expected `min` or `max`"))
+                            }
+                        })?;
+                        if min.is_none() && max.is_none() {
+                            return Err(meta.error("expected at least one of `min` or `max`"));
+                        }
+                        validations.push((typed_arg.pat.clone(), kind, min, max));
+                        Ok(())
                     } else {
                         Err(meta.error(
-                            "Unrecognized #[server] attribute, expected \
-                             #[server(default)]",
+                            "Unrecognized #[server] attribute, expected #[server(default)], \
+                             #[server(sensitive)], #[server(length(..))] or #[server(range(..))]",
                         ))
                     }
                 })?;
@@ -236,9 +279,13 @@ pub fn server_macro_impl(
     let inventory = if cfg!(feature = "ssr") {
         quote! {
             #server_fn_path::inventory::submit! {{
-                use #server_fn_path::ServerFn;
+                use #server_fn_path::{codec::Encoding, ServerFn};
                 #server_fn_path::ServerFnTraitObj::new(
                     #struct_name::PATH,
+                    <#struct_name as ServerFn>::InputEncoding::METHOD,
+                    <#struct_name as ServerFn>::InputEncoding::CONTENT_TYPE,
+                    #struct_name::GUARDS,
+                    #struct_name::BODY_LIMIT,
                     |req| {
                         Box::pin(#struct_name::run_on_server(req))
                     },
@@ -267,6 +314,120 @@ pub fn server_macro_impl(
         }
     };
 
+    // typed check on the decoded arguments, which only runs on the server
+    let check = if cfg!(feature = "ssr") {
+        check.map(|check| {
+            quote! {
+                fn check(&self) -> impl std::future::Future<Output = Result<(), #server_fn_path::ServerFnError<Self::Error>>> + Send {
+                    #check(self)
+                }
+            }
+        })
+    } else {
+        None
+    };
+
+    // guards against the incoming request, which only run on the server
+    let guard_sources = guards
+        .iter()
+        .map(|guard| guard.to_token_stream().to_string())
+        .collect::<Vec<_>>();
+    let guard = (cfg!(feature = "ssr") && !guards.is_empty()).then(|| {
+        let names = (0..guards.len())
+            .map(|idx| Ident::new(&format!("guard_{idx}"), Span::call_site()))
+            .collect::<Vec<_>>();
+        quote! {
+            fn guard(
+                req: &Self::ServerRequest,
+            ) -> impl std::future::Future<Output = Result<(), #server_fn_path::ServerFnError<Self::Error>>> + Send + 'static {
+                #( let #names = (#guards)(req); )*
+                async move {
+                    #( #names.await.map_err(#server_fn_path::guard::GuardRejection::into_error)?; )*
+                    Ok(())
+                }
+            }
+        }
+    });
+
+    // the largest request body that the server function accepts, in bytes
+    let body_limit = body_limit.map(|limit| {
+        quote! {
+            const BODY_LIMIT: Option<u64> = Some(#limit);
+        }
+    });
+
+    // validation of the decoded arguments, which only runs on the server
+    let validate_impl = (!validations.is_empty()).then(|| {
+        let rules = validations.iter().map(|(field, kind, min, max)| {
+            let field_name = field.to_token_stream().to_string();
+            let min = min
+                .as_ref()
+                .map(|min| quote! { Some(#min) })
+                .unwrap_or_else(|| quote! { None });
+            let max = max
+                .as_ref()
+                .map(|max| quote! { Some(#max) })
+                .unwrap_or_else(|| quote! { None });
+            quote! {
+                if let Err(message) = #server_fn_path::validate::#kind(&self.#field, #min, #max) {
+                    errors.add(#field_name, message);
+                }
+            }
+        });
+        quote! {
+            impl #server_fn_path::validate::Validate for #struct_name {
+                fn validate(&self) -> Result<(), #server_fn_path::validate::ValidationErrors> {
+                    let mut errors = #server_fn_path::validate::ValidationErrors::default();
+                    #(#rules)*
+                    errors.into_result()
+                }
+            }
+        }
+    });
+    let validate_args = (cfg!(feature = "ssr") && (validate || validate_impl.is_some())).then(|| {
+        quote! {
+            fn validate_args(&self) -> Result<(), #server_fn_path::ServerFnError<Self::Error>> {
+                #server_fn_path::validate::Validate::validate(self)
+                    .map_err(#server_fn_path::ServerFnError::Validation)
+            }
+        }
+    });
+
+    // args marked #[server(sensitive)] are left out of the Debug impl, and so out of logs
+    let (derive_debug, debug_impl) = if sensitive_fields.is_empty() {
+        (quote! { Debug, }, None)
+    } else {
+        let debug_fields = field_names.iter().map(|field| {
+            let field_name = field.to_token_stream().to_string();
+            let sensitive = sensitive_fields
+                .iter()
+                .any(|sensitive| sensitive.to_token_stream().to_string() == field_name);
+            if sensitive {
+                quote! { .field(#field_name, &"[redacted]") }
+            } else {
+                quote! { .field(#field_name, &self.#field) }
+            }
+        });
+        let struct_name_as_str = struct_name.to_string();
+        let debug_impl = quote! {
+            impl std::fmt::Debug for #struct_name {
+                fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                    f.debug_struct(#struct_name_as_str)
+                        #(#debug_fields)*
+                        .finish()
+                }
+            }
+        };
+        (quote! {}, Some(debug_impl))
+    };
+    let debug_args = cfg!(feature = "ssr").then(|| {
+        quote! {
+            fn debug_args(&self) -> Option<String> {
+                Some(format!("{self:?}"))
+            }
+        }
+    });
+
     // the actual function definition
     let func = if cfg!(feature = "ssr") {
         quote! {
@@ -289,8 +450,21 @@ pub fn server_macro_impl(
         }
     };
 
+    // WebSocket server functions are called by opening a WebSocket, rather than sending a request
+    let run_on_client = input_is_websocket.then(|| {
+        quote! {
+            fn run_on_client(
+                self,
+            ) -> impl std::future::Future<
+                Output = Result<Self::Output, #server_fn_path::ServerFnError<Self::Error>>,
+            > + Send {
+                #server_fn_path::codec::run_websocket_on_client(self)
+            }
+        }
+    });
+
     // TODO rkyv derives
-    let derives = if input_is_multipart {
+    let derives = if input_is_multipart || input_is_websocket {
         quote! {}
     } else if input_is_rkyv {
         todo!("implement derives for Rkyv")
@@ -299,15 +473,18 @@ pub fn server_macro_impl(
             Clone, #server_fn_path::serde::Serialize, #server_fn_path::serde::Deserialize
         }
     };
-    let serde_path = (!input_is_multipart && !input_is_rkyv).then(|| {
+    let serde_path = (!input_is_multipart && !input_is_websocket && !input_is_rkyv).then(|| {
         quote! {
             #[serde(crate = #serde_path)]
         }
     });
 
     // TODO reqwest
-    let client = quote! {
-        #server_fn_path::client::browser::BrowserClient
+    let client = match client {
+        Some(client) => quote! { #client },
+        None => quote! {
+            #server_fn_path::client::browser::BrowserClient
+        },
     };
 
     // TODO Actix etc
@@ -321,7 +498,7 @@ pub fn server_macro_impl(
         }
     } else if cfg!(feature = "actix") {
         quote! {
-            #server_fn_path::actix_export::HttpRequest
+            #server_fn_path::request::actix::ActixRequest
         }
     } else {
         return Err(syn::Error::new(Span::call_site(), "If the `ssr` feature is enabled, either the `actix` or `axum` features should also be enabled."));
@@ -336,7 +513,7 @@ pub fn server_macro_impl(
         }
     } else if cfg!(feature = "actix") {
         quote! {
-            #server_fn_path::actix_export::HttpResponse
+            #server_fn_path::response::actix::ActixResponse
         }
     } else {
         return Err(syn::Error::new(Span::call_site(), "If the `ssr` feature is enabled, either the `actix` or `axum` features should also be enabled."));
@@ -365,12 +542,41 @@ pub fn server_macro_impl(
     // only emit the dummy (unmodified server-only body) for the server build
     let dummy = cfg!(feature = "ssr").then_some(dummy);
     let middlewares = if cfg!(feature = "ssr") {
+        // the built-in limits are innermost, so that they only cover the server function
+        let timeout = timeout.map(|millis| {
+            quote! {
+                std::sync::Arc::new(#server_fn_path::middleware::timeout::TimeoutLayer::new(
+                    std::time::Duration::from_millis(#millis)
+                ))
+            }
+        });
+        // the limit is kept in a static so that it's shared if the middleware is rebuilt
+        let max_concurrent = max_concurrent.map(|max| {
+            let queue_timeout = queue_timeout.map(|millis| {
+                quote! { .queue_timeout(std::time::Duration::from_millis(#millis)) }
+            });
+            quote! {{
+                static LIMIT: std::sync::OnceLock<
+                    #server_fn_path::middleware::concurrency::ConcurrencyLimitLayer
+                > = std::sync::OnceLock::new();
+                std::sync::Arc::new(
+                    LIMIT
+                        .get_or_init(|| {
+                            #server_fn_path::middleware::concurrency::ConcurrencyLimitLayer::new(#max)
+                                #queue_timeout
+                        })
+                        .clone()
+                )
+            }}
+        });
+        let middlewares = timeout
+            .into_iter()
+            .chain(max_concurrent)
+            .chain(middlewares.iter().map(|middleware| {
+                quote! { std::sync::Arc::new(#middleware) }
+            }));
         quote! {
-            vec![
-                #(
-                    std::sync::Arc::new(#middlewares),
-                ),*
-            ]
+            vec![#(#middlewares),*]
         }
     } else {
         quote! { vec![] }
@@ -379,7 +585,7 @@ pub fn server_macro_impl(
     Ok(quote::quote! {
         #args_docs
         #docs
-        #[derive(Debug, #derives)]
+        #[derive(#derive_debug #derives)]
         #serde_path
         pub struct #struct_name {
             #(#fields),*
@@ -387,9 +593,15 @@ pub fn server_macro_impl(
 
         #from_impl
 
+        #validate_impl
+
+        #debug_impl
+
         impl #server_fn_path::ServerFn for #struct_name {
             // TODO prefix
             const PATH: &'static str = #path;
+            const GUARDS: &'static [&'static str] = &[#(#guard_sources),*];
+            #body_limit
 
             type Client = #client;
             type ServerRequest = #req;
@@ -403,7 +615,17 @@ pub fn server_macro_impl(
                 #middlewares
             }
 
+            #guard
+
+            #validate_args
+
+            #debug_args
+
+            #check
+
             #run_body
+
+            #run_on_client
         }
 
         #inventory
@@ -494,6 +716,16 @@ struct ServerFnArgs {
     input: Option<Ident>,
     output: Option<Ident>,
     fn_path: Option<Literal>,
+    check: Option<Path>,
+    validate: bool,
+    guards: Vec<syn::Expr>,
+    body_limit: Option<syn::Expr>,
+    /// In milliseconds.
+    timeout: Option<u64>,
+    max_concurrent: Option<syn::Expr>,
+    /// In milliseconds.
+    queue_timeout: Option<u64>,
+    client: Option<Type>,
 }
 
 impl Parse for ServerFnArgs {
@@ -507,6 +739,14 @@ impl Parse for ServerFnArgs {
         // new arguments: can only be keyed by name
         let mut input: Option<Ident> = None;
         let mut output: Option<Ident> = None;
+        let mut check: Option<Path> = None;
+        let mut validate = false;
+        let mut guards: Vec<syn::Expr> = Vec::new();
+        let mut body_limit: Option<syn::Expr> = None;
+        let mut timeout: Option<u64> = None;
+        let mut max_concurrent: Option<syn::Expr> = None;
+        let mut queue_timeout: Option<u64> = None;
+        let mut client: Option<Type> = None;
 
         let mut use_key_and_value = false;
         let mut arg_pos = 0;
@@ -580,9 +820,68 @@ impl Parse for ServerFnArgs {
                             ));
                         }
                         output = Some(stream.parse()?);
+                    } else if key == "check" {
+                        if check.is_some() {
+                            return Err(syn::Error::new(
+                                key.span(),
+                                "keyword argument repeated: `check`",
+                            ));
+                        }
+                        check = Some(stream.parse()?);
+                    } else if key == "guard" {
+                        guards.push(stream.parse()?);
+                    } else if key == "body_limit" {
+                        if body_limit.is_some() {
+                            return Err(syn::Error::new(
+                                key.span(),
+                                "keyword argument repeated: `body_limit`",
+                            ));
+                        }
+                        body_limit = Some(stream.parse()?);
+                    } else if key == "timeout" {
+                        if timeout.is_some() {
+                            return Err(syn::Error::new(
+                                key.span(),
+                                "keyword argument repeated: `timeout`",
+                            ));
+                        }
+                        timeout = Some(parse_duration(&stream.parse()?)?);
+                    } else if key == "max_concurrent" {
+                        if max_concurrent.is_some() {
+                            return Err(syn::Error::new(
+                                key.span(),
+                                "keyword argument repeated: `max_concurrent`",
+                            ));
+                        }
+                        max_concurrent = Some(stream.parse()?);
+                    } else if key == "queue_timeout" {
+                        if queue_timeout.is_some() {
+                            return Err(syn::Error::new(
+                                key.span(),
+                                "keyword argument repeated: `queue_timeout`",
+                            ));
+                        }
+                        queue_timeout = Some(parse_duration(&stream.parse()?)?);
+                    } else if key == "client" {
+                        if client.is_some() {
+                            return Err(syn::Error::new(
+                                key.span(),
+                                "keyword argument repeated: `client`",
+                            ));
+                        }
+                        client = Some(stream.parse()?);
                     } else {
                         return Err(lookahead.error());
                     }
+                } else if key_or_value == "validate" {
+                    if validate {
+                        return Err(syn::Error::new(
+                            key_or_value.span(),
+                            "keyword argument repeated: `validate`",
+                        ));
+                    }
+                    use_key_and_value = true;
+                    validate = true;
                 } else {
                     let value = key_or_value;
                     if use_key_and_value {
@@ -622,6 +921,13 @@ impl Parse for ServerFnArgs {
             }
         }
 
+        if queue_timeout.is_some() && max_concurrent.is_none() {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "`queue_timeout` can only be used along with `max_concurrent`",
+            ));
+        }
+
         // parse legacy encoding into input/output
         if let Some(encoding) = encoding {
             match encoding.to_string().to_lowercase().as_str() {
@@ -651,6 +957,14 @@ impl Parse for ServerFnArgs {
             input,
             output,
             fn_path,
+            check,
+            validate,
+            guards,
+            body_limit,
+            timeout,
+            max_concurrent,
+            queue_timeout,
+            client,
         })
     }
 }
@@ -750,6 +1064,13 @@ impl ServerFnBody {
             block,
             ..
         } = &self;
+        // #[server(..)] attributes on arguments are only meaningful to this macro
+        let mut inputs = inputs.clone();
+        for input in inputs.iter_mut() {
+            if let FnArg::Typed(typed) = input {
+                typed.attrs.retain(|attr| !attr.path().is_ident("server"));
+            }
+        }
         quote! {
             #[doc(hidden)]
             #(#attrs)*
@@ -759,6 +1080,33 @@ impl ServerFnBody {
     }
 }
 
+/// Parses a duration like `"500ms"`, `"5s"`, `"2m"` or `"1h"` into milliseconds.
+fn parse_duration(lit: &LitStr) -> Result<u64> {
+    let value = lit.value();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let millis_per_unit = match unit.trim() {
+        "ms" => 1,
+        "s" => 1_000,
+        "m" => 60_000,
+        "h" => 3_600_000,
+        _ => 0,
+    };
+    match amount
+        .parse::<u64>()
+        .ok()
+        .and_then(|amount| amount.checked_mul(millis_per_unit))
+    {
+        Some(millis) if millis_per_unit > 0 => Ok(millis),
+        _ => Err(syn::Error::new(
+            lit.span(),
+            "expected a duration like \"500ms\", \"5s\", \"2m\" or \"1h\"",
+        )),
+    }
+}
+
 /// Returns either the path of the codec (if it's a builtin) or the
 /// original ident.
 fn codec_ident(server_fn_path: Option<&Path>, ident: Ident) -> TokenStream2 {
@@ -773,6 +1121,8 @@ fn codec_ident(server_fn_path: Option<&Path>, ident: Ident) -> TokenStream2 {
             "Streaming",
             "StreamingText",
             "MultipartFormData",
+            "Websocket",
+            "WebsocketText",
         ]
         .contains(&str.as_str())
         {